@@ -1,7 +1,2 @@
-function __inkjet_dynamic_complete
-    complete -c inkjet -e
-    inkjet inkjet-dynamic-completions fish | source
-end
-
-# Set up the dynamic completion
-complete -c inkjet -f -a "(__inkjet_dynamic_complete)"
+# @BIN@ is asked for completions of the nearest inkfile each time tab is pressed.
+INKJET_COMPLETE=fish @BIN@ | source
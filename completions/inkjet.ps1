@@ -0,0 +1,2 @@
+# @BIN@ is asked for completions of the nearest inkfile each time tab is pressed.
+$env:INKJET_COMPLETE = "powershell"; @BIN@ | Out-String | Invoke-Expression; Remove-Item Env:\INKJET_COMPLETE
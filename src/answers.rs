@@ -0,0 +1,143 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::command::CommandBlock;
+use crate::executor::hash_source;
+use crate::utils;
+
+/// Answers are the values last given to the interactive prompts of the commands of an inkfile.
+/// They become the defaults of the prompts the next time the command runs interactively.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Answers {
+    /// The path of the inkfile the answers were given for
+    inkfile: String,
+    /// The values of each command by its full name, i.e. `services start`, then by flag or arg name
+    commands: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// Returns where the answers for an inkfile are saved, i.e. `~/.local/share/inkjet/answers/<hash>.json`
+fn answers_path(inkfile_path: &str) -> Option<PathBuf> {
+    Some(
+        utils::xdg_dir("XDG_DATA_HOME", ".local/share")?
+            .join("inkjet/answers")
+            .join(format!("{}.json", hash_source(inkfile_path))),
+    )
+}
+
+impl Answers {
+    /// Loads the answers saved for an inkfile. It is empty if none were saved.
+    pub fn load(inkfile_path: &str) -> Answers {
+        answers_path(inkfile_path)
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|contents| serde_json::from_slice::<Answers>(&contents).ok())
+            .filter(|answers| answers.inkfile == inkfile_path)
+            .unwrap_or_else(|| Answers {
+                inkfile: inkfile_path.to_string(),
+                ..Answers::default()
+            })
+    }
+
+    /// Returns the values last given for a command
+    pub fn of(&self, command: &str) -> BTreeMap<String, String> {
+        self.commands.get(command).cloned().unwrap_or_default()
+    }
+
+    /// Replaces the values of a command with those of cmd. Secret flags and empty values are left out.
+    pub fn record(&mut self, command: &str, cmd: &CommandBlock) {
+        let mut values = BTreeMap::new();
+        // verbose is never prompted for
+        for flag in cmd
+            .named_flags
+            .iter()
+            .filter(|f| !f.secret && f.name != "verbose")
+        {
+            if !flag.takes_value {
+                // Remember a switch that was turned off too, so its prompt defaults to no
+                let on = flag.val == "true";
+                values.insert(flag.name.clone(), on.to_string());
+            } else if !flag.val.is_empty() {
+                values.insert(flag.name.clone(), flag.val.clone());
+            }
+        }
+        for arg in cmd.args.iter().filter(|a| !a.val.is_empty()) {
+            values.insert(arg.name.clone(), arg.val.clone());
+        }
+        self.commands.insert(command.to_string(), values);
+    }
+
+    /// Writes the answers to the user's data directory
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = answers_path(&self.inkfile) else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_vec(self).map_err(io::Error::other)?)
+    }
+}
+
+/// Removes the answers saved for an inkfile. Returns false if there were none.
+pub fn forget(inkfile_path: &str) -> io::Result<bool> {
+    let Some(path) = answers_path(inkfile_path) else {
+        return Ok(false);
+    };
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inkfile::Inkfile;
+
+    #[test]
+    fn records_values_except_secrets() {
+        let inkfile = Inkfile::parse(
+            r#"
+## deploy (cluster)
+
+**OPTIONS**
+
+- flag: --namespace |string| The namespace
+- flag: --token |string secret| The API token
+- flag: --dry-run |bool| Only print the changes
+- flag: --tag |string| The image tag
+
+```sh
+echo deploy
+```
+"#,
+        )
+        .expect("valid inkfile");
+        let mut cmd = inkfile.find("deploy").expect("command exists").clone();
+        for flag in &mut cmd.named_flags {
+            flag.val = match flag.name.as_str() {
+                "namespace" => "web".to_string(),
+                "token" => "hunter2".to_string(),
+                _ => "".to_string(),
+            };
+        }
+        if let Some(arg) = cmd.args.first_mut() {
+            arg.val = "prod".to_string();
+        }
+        let mut answers = Answers::default();
+        answers.record("deploy", &cmd);
+        let values = answers.of("deploy");
+        assert_eq!(values.get("cluster").map(|v| v.as_str()), Some("prod"));
+        assert_eq!(values.get("namespace").map(|v| v.as_str()), Some("web"));
+        assert_eq!(values.get("dry-run").map(|v| v.as_str()), Some("false"));
+        assert!(!values.contains_key("token"));
+        assert!(!values.contains_key("tag"));
+        assert!(answers.of("other").is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use crate::command::CommandBlock;
+use crate::import::Source;
+use crate::inkfile::Inkfile;
+use crate::parser::InkjetConfig;
+
+/// Marks the end of an executable that has a bundled inkfile appended to it.
+/// The payload is followed by its length as a little-endian u64 and then this marker.
+const MAGIC: &[u8; 8] = b"INKJETB1";
+const TRAILER_LEN: u64 = 16;
+/// The version of the Bundle payload. Bump it when the payload changes shape,
+/// so an executable never reads a bundle written by an incompatible inkjet.
+const PAYLOAD_VERSION: u32 = 2;
+
+/// Bundle is the payload that `--inkjet-bundle` appends to a copy of the inkjet executable.
+/// It holds the parsed inkfile, so the bundled binary does not read or import any markdown files at runtime.
+/// Paths are relative to the directory of the root inkfile. The root inkfile itself has an empty path.
+#[derive(Debug, Serialize, Deserialize)]
+struct Bundle {
+    schema_version: u32,
+    config: InkjetConfig,
+    root: CommandBlock,
+    sources: Vec<Source>,
+}
+
+/// Writes a copy of the running executable with the inkfile appended to output.
+/// If the running executable is itself a bundle, its payload is replaced.
+pub fn write(inkfile: &Inkfile, output: &Path) -> io::Result<()> {
+    let exe = env::current_exe()?;
+    let mut bytes = fs::read(&exe)?;
+    if let Some(payload_start) = payload_start(&bytes) {
+        bytes.truncate(payload_start);
+    }
+    // The paths of the build machine are not shipped
+    let root_dir = Path::new(&inkfile.path).parent().unwrap_or(Path::new(""));
+    let relative = |path: &str| {
+        if path == inkfile.path {
+            String::new()
+        } else {
+            relative_path(root_dir, Path::new(path))
+        }
+    };
+    let mut root = inkfile.root.clone();
+    relocate(&mut root, &relative);
+    let bundle = Bundle {
+        schema_version: PAYLOAD_VERSION,
+        config: inkfile.config.clone(),
+        root,
+        sources: inkfile
+            .sources
+            .iter()
+            .map(|s| Source {
+                path: relative(&s.path),
+                contents: s.contents.clone(),
+            })
+            .collect(),
+    };
+    let payload = serde_json::to_vec(&bundle).map_err(io::Error::other)?;
+    bytes.extend_from_slice(&payload);
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(MAGIC);
+    fs::write(output, bytes)?;
+
+    #[cfg(not(windows))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = fs::metadata(output)?.permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(output, perms)?;
+    }
+    Ok(())
+}
+
+/// Returns the inkfile bundled into the running executable, if any. See `read`.
+pub fn embedded() -> Option<Inkfile> {
+    read(&env::current_exe().ok()?)
+}
+
+/// Returns the inkfile bundled into the executable at exe, if it has one that this version of inkjet can read.
+/// Its path and the path of its root inkfile are the path of the executable, and its commands run in the current directory.
+pub fn read(exe: &Path) -> Option<Inkfile> {
+    let payload = read_payload(exe).ok()??;
+    let bundle: Bundle = serde_json::from_slice(&payload).ok()?;
+    if bundle.schema_version != PAYLOAD_VERSION {
+        return None;
+    }
+    let mut config = bundle.config;
+    // The directories of the bundled inkfiles do not exist where the binary is installed
+    config.fixed_dir = false;
+    let path = exe.to_string_lossy().to_string();
+    let mut sources = bundle.sources;
+    if let Some(root) = sources.first_mut() {
+        root.path = path.clone();
+    }
+    Some(Inkfile {
+        path,
+        config,
+        root: bundle.root,
+        sources,
+    })
+}
+
+/// Rewrites the `inkjet_file` of a command and its subcommands
+fn relocate(cmd: &mut CommandBlock, relative: &impl Fn(&str) -> String) {
+    if !cmd.inkjet_file.is_empty() {
+        cmd.inkjet_file = relative(&cmd.inkjet_file);
+    }
+    for sub in &mut cmd.subcommands {
+        relocate(sub, relative);
+    }
+}
+
+/// Returns path relative to dir. Both are absolute, so they share at least the root.
+fn relative_path(dir: &Path, path: &Path) -> String {
+    let dir: Vec<Component> = dir.components().collect();
+    let path: Vec<Component> = path.components().collect();
+    let common = dir.iter().zip(&path).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..dir.len() {
+        relative.push("..");
+    }
+    relative.extend(path.iter().skip(common));
+    relative.to_string_lossy().to_string()
+}
+
+/// Reads only the trailer and payload of an executable
+fn read_payload(exe: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut file = File::open(exe)?;
+    let file_len = file.metadata()?.len();
+    if file_len < TRAILER_LEN {
+        return Ok(None);
+    }
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    file.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+    file.read_exact(&mut trailer)?;
+    let (len, magic) = trailer.split_at(8);
+    if magic != MAGIC {
+        return Ok(None);
+    }
+    let payload_len = u64::from_le_bytes(len.try_into().unwrap_or_default());
+    if payload_len > file_len - TRAILER_LEN {
+        return Ok(None);
+    }
+    let mut payload = vec![0u8; payload_len as usize];
+    file.seek(SeekFrom::End(-((TRAILER_LEN + payload_len) as i64)))?;
+    file.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+/// Returns the offset of the payload in an executable's bytes
+fn payload_start(bytes: &[u8]) -> Option<usize> {
+    let trailer_start = bytes.len().checked_sub(TRAILER_LEN as usize)?;
+    let (len, magic) = bytes.get(trailer_start..)?.split_at(8);
+    if magic != MAGIC {
+        return None;
+    }
+    let payload_len = u64::from_le_bytes(len.try_into().ok()?) as usize;
+    trailer_start.checked_sub(payload_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn makes_paths_relative_to_the_root_inkfile() {
+        let dir = Path::new("/home/builder/project");
+        let cases = [
+            (
+                "/home/builder/project/services/api/inkjet.md",
+                "services/api/inkjet.md",
+            ),
+            ("/home/builder/shared/inkjet.md", "../shared/inkjet.md"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(relative_path(dir, Path::new(path)), expected);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::command::CommandBlock;
+use crate::executor::hash_source;
+use crate::import::{self, ImportSpec, Source};
+use crate::inkfile::Inkfile;
+use crate::parser::{self, InkjetConfig};
+use crate::utils;
+
+/// Disables the cache when set to a non-empty value
+const NO_CACHE_VAR: &str = "INKJET_NO_CACHE";
+
+/// CacheEntry is a parsed inkfile along with the state of every file that contributed to it.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    /// The inkjet version that wrote the entry. Entries written by other versions are ignored.
+    version: String,
+    /// The contributing inkfiles and their directories. A change to any of them invalidates the entry.
+    stamps: Vec<Stamp>,
+    config: InkjetConfig,
+    root: CommandBlock,
+    sources: Vec<Source>,
+}
+
+/// Stamp is the modification time and size of a file or directory when the entry was written.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Stamp {
+    path: String,
+    modified: u128,
+    len: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            path: path.to_string_lossy().to_string(),
+            modified: modified.as_nanos(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Returns false if INKJET_NO_CACHE is set.
+pub fn enabled() -> bool {
+    env::var_os(NO_CACHE_VAR).is_none_or(|v| v.is_empty())
+}
+
+/// Returns the directory cache entries are stored in, i.e. `~/.cache/inkjet`
+pub fn dir() -> Option<PathBuf> {
+    Some(utils::xdg_dir("XDG_CACHE_HOME", ".cache")?.join("inkjet"))
+}
+
+fn entry_path(inkfile_path: &str) -> Option<PathBuf> {
+    Some(dir()?.join(format!("{}.json", hash_source(inkfile_path))))
+}
+
+/// Returns the cached parse of the inkfile at inkfile_path if no contributing file has changed since it was stored.
+pub fn load(inkfile_path: &str) -> Option<Inkfile> {
+    let contents = fs::read(entry_path(inkfile_path)?).ok()?;
+    let entry: CacheEntry = serde_json::from_slice(&contents).ok()?;
+    if entry.version != env!("CARGO_PKG_VERSION")
+        || entry.sources.first().map(|s| s.path.as_str()) != Some(inkfile_path)
+    {
+        return None;
+    }
+    if !entry
+        .stamps
+        .iter()
+        .all(|stamp| Stamp::of(Path::new(&stamp.path)).as_ref() == Some(stamp))
+    {
+        return None;
+    }
+    Some(Inkfile {
+        path: inkfile_path.to_string(),
+        config: entry.config,
+        root: entry.root,
+        sources: entry.sources,
+    })
+}
+
+/// Stores the parsed inkfile. Inkfiles read from stdin are not stored.
+pub fn store(inkfile: &Inkfile) -> io::Result<()> {
+    let Some(path) = entry_path(&inkfile.path) else {
+        return Ok(());
+    };
+    // Directories are stamped too, so adding an inkfile next to a contributing one is noticed
+    let mut paths = BTreeSet::new();
+    for source in &inkfile.sources {
+        let source_path = Path::new(&source.path);
+        if !source_path.is_file() {
+            return Ok(());
+        }
+        paths.insert(source_path.to_path_buf());
+        if let Some(parent) = source_path.parent() {
+            paths.insert(parent.to_path_buf());
+        }
+        // `import = ["all"]` finds inkfiles anywhere below, so every directory it searches
+        // and every ignore file it reads is stamped
+        let Ok(config) = parser::parse_config(&source.contents, false) else {
+            return Ok(());
+        };
+        if config
+            .import
+            .iter()
+            .any(|spec| ImportSpec::parse(spec) == Ok(ImportSpec::All))
+        {
+            let Ok(walked) = import::discovery_paths(source_path, &config) else {
+                return Ok(());
+            };
+            paths.extend(walked);
+        }
+    }
+    let stamps = paths.iter().filter_map(|p| Stamp::of(p)).collect();
+    let entry = CacheEntry {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        stamps,
+        config: inkfile.config.clone(),
+        root: inkfile.root.clone(),
+        sources: inkfile.sources.clone(),
+    };
+    let json = serde_json::to_vec(&entry).map_err(io::Error::other)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Write to a temporary file first so concurrent shells never read a partial entry
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp, json)?;
+    fs::rename(&tmp, &path)
+}
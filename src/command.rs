@@ -1,15 +1,23 @@
 // Copyright 2020 Brandon Kalinowski (brandonkal)
 // SPDX-License-Identifier: MIT
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
 /// CommandBlock represents a target constructed from the inkjet file parsing process.
 /// It provides all the options required to then execute the target.
-#[derive(Debug, Clone)]
+/// It is serialized by `--inkjet-dump`. Values that are only populated when applying matches are skipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandBlock {
     /// cmd_level represents the depth of a command. Subcommands have a higher cmd_level.
     pub cmd_level: u8,
     /// name is the name of this CommandBlock
     pub name: String,
     /// aliases represent alternative ways to call the given command.
+    /// They are joined with `//` and serialized as a list.
+    #[serde(
+        serialize_with = "serialize_aliases",
+        deserialize_with = "deserialize_aliases"
+    )]
     pub aliases: String,
     /// desc defines a description of the CommandBlock. It is displayed in the CLI help text.
     pub desc: String,
@@ -31,7 +39,27 @@ pub struct CommandBlock {
     pub inkjet_file: String,
     /// validation_error_msg is typically empty. When it contains a value, it typically means that the user tried to provide
     /// an incorrect type to an named flag.
+    #[serde(skip)]
     pub validation_error_msg: String,
+    /// overrides is set by a `{override}` marker at the end of the heading.
+    /// An overriding CommandBlock replaces an earlier definition of the same name instead of being merged with it.
+    pub overrides: bool,
+    /// confirm is the question asked before the command runs, set by a `confirm` option.
+    #[serde(default)]
+    pub confirm: String,
+    /// confirm_phrase is a word that must be typed before the command runs, set by a `confirm_phrase` option.
+    #[serde(default)]
+    pub confirm_phrase: String,
+}
+
+fn serialize_aliases<S: Serializer>(aliases: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    let list: Vec<&str> = aliases.split("//").filter(|a| !a.is_empty()).collect();
+    list.serialize(serializer)
+}
+
+fn deserialize_aliases<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let list: Vec<String> = Vec::deserialize(deserializer)?;
+    Ok(list.join("//"))
 }
 
 impl PartialEq for CommandBlock {
@@ -57,7 +85,22 @@ impl CommandBlock {
             end: 0,
             inkjet_file: "".to_string(),
             validation_error_msg: "".to_string(),
+            overrides: false,
+            confirm: "".to_string(),
+            confirm_phrase: "".to_string(),
+        }
+    }
+    /// Finds a descendant command by its space separated path relative to this command, i.e. `services start`.
+    /// Each word may be a command name or one of its aliases.
+    pub fn find(&self, path: &str) -> Option<&CommandBlock> {
+        let mut cmd = self;
+        for word in path.split_whitespace() {
+            cmd = cmd
+                .subcommands
+                .iter()
+                .find(|c| c.name == word || c.aliases.split("//").any(|a| a == word))?;
         }
+        Some(cmd)
     }
     #[must_use]
     /// call build to add the default verbose flag to this CommandBlock's option_flags
@@ -74,20 +117,26 @@ impl CommandBlock {
                 validate_as_number: false,
                 choices: vec![],
                 required: false,
+                secret: false,
                 val: "".to_string(),
+                complete: None,
             });
         }
         self
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 /// Script represents the source for a CommandBlock
 pub struct Script {
     /// The executor to run the source with i.e. sh, node, ruby, python, etc...
     pub executor: String,
     /// The script source to execute
     pub source: String,
+    /// The byte offset of the first line of the source in the inkfile
+    pub offset: usize,
+    /// The line number of the first line of the source in the inkfile, starting at 1. It is 0 if the script has no source.
+    pub line: usize,
 }
 
 impl Script {
@@ -97,6 +146,8 @@ impl Script {
         Self {
             executor: "".to_string(),
             source: "".to_string(),
+            offset: 0,
+            line: 0,
         }
     }
     /// Returns true if the script is non-empty
@@ -105,12 +156,13 @@ impl Script {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Arg represents an intermediate representation of a positional arg.
 pub struct Arg {
     /// The name of the Arg
     pub name: String,
     /// The value of the Arg. This is an empty string when parsed and populated after matches are applied
+    #[serde(skip)]
     pub val: String,
     /// If a required arg is not supplied, the CLI will exit with an error.
     pub required: bool,
@@ -120,6 +172,9 @@ pub struct Arg {
     pub multiple: bool,
     /// Set last to true for the last arg after the -- separator
     pub last: bool,
+    /// A script run at completion time that prints the candidate values, one per line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub complete: Option<Script>,
 }
 
 impl Arg {
@@ -133,11 +188,12 @@ impl Arg {
             default,
             multiple,
             last: false,
+            complete: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 /// NamedFlag is an intermediate representation of a named flag
 pub struct NamedFlag {
     /// The name of the flag.
@@ -159,8 +215,15 @@ pub struct NamedFlag {
     pub choices: Vec<String>,
     /// required is true if the script should fail without the flag
     pub required: bool,
+    /// A secret flag is prompted for without echo. Its value is never printed.
+    #[serde(default)]
+    pub secret: bool,
     /// The value of the flag. Is empty after parsing a markdown document. This value is populated when applying matches.
+    #[serde(skip)]
     pub val: String,
+    /// A script run at completion time that prints the candidate values, one per line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub complete: Option<Script>,
 }
 
 impl NamedFlag {
@@ -177,7 +240,9 @@ impl NamedFlag {
             validate_as_number: false,
             choices: vec![],
             required: false,
+            secret: false,
             val: "".to_string(),
+            complete: None,
         }
     }
 }
@@ -0,0 +1,121 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::utils::xdg_dir;
+
+/// Returns the wrapper script that registers completions for a shell.
+/// The wrappers call back into the program each time tab is pressed, so they never go stale.
+/// In the templates, `@BIN@` is the name of the program and `@FUNCTION@` is the name clap gives the zsh completer,
+/// which cannot contain `-`.
+pub fn wrapper(shell: &str, program: &str) -> Option<String> {
+    let script = match shell {
+        "bash" => include_str!("../completions/inkjet.bash"),
+        "zsh" => include_str!("../completions/inkjet.zsh"),
+        "fish" => include_str!("../completions/inkjet.fish"),
+        "elvish" => include_str!("../completions/inkjet.elv"),
+        "nushell" | "nu" => include_str!("../completions/inkjet.nu"),
+        "powershell" => include_str!("../completions/inkjet.ps1"),
+        _ => return None,
+    };
+    Some(
+        script
+            .replace("@FUNCTION@", &program.replace('-', "_"))
+            .replace("@BIN@", program),
+    )
+}
+
+/// Returns where the wrapper for a shell is installed, along with a hint if the shell must be configured to load it.
+/// PowerShell has no completion directory, so it returns None.
+pub fn install_path(shell: &str, program: &str) -> Option<(PathBuf, Option<String>)> {
+    let config = xdg_dir("XDG_CONFIG_HOME", ".config")?;
+    match shell {
+        "bash" => Some((
+            xdg_dir("XDG_DATA_HOME", ".local/share")?
+                .join("bash-completion/completions")
+                .join(program),
+            None,
+        )),
+        "zsh" => {
+            let dir = xdg_dir("ZDOTDIR", "")?.join(".zfunc");
+            let hint = format!(
+                "Add `fpath+=({})` before `compinit` in your .zshrc",
+                dir.display()
+            );
+            Some((dir.join(format!("_{program}")), Some(hint)))
+        }
+        "fish" => Some((
+            config
+                .join("fish/completions")
+                .join(format!("{program}.fish")),
+            None,
+        )),
+        "elvish" => Some((
+            config
+                .join("elvish/lib")
+                .join(format!("{program}-completions.elv")),
+            Some(format!("Add `use {program}-completions` to your rc.elv")),
+        )),
+        "nushell" | "nu" => Some((
+            config
+                .join("nushell/autoload")
+                .join(format!("{program}.nu")),
+            None,
+        )),
+        _ => None,
+    }
+}
+
+/// Writes the wrapper for a shell into the user's completion directory and returns its path and hint.
+pub fn install(shell: &str, program: &str) -> io::Result<(PathBuf, Option<String>)> {
+    let script = wrapper(shell, program).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Unsupported shell: {shell}. Expected bash, zsh, fish, elvish, nushell or powershell."
+            ),
+        )
+    })?;
+    let (path, hint) = install_path(shell, program).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{shell} has no completion directory. Add this line to your profile:\n{script}"
+            ),
+        )
+    })?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, script)?;
+    Ok((path, hint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_wrappers_for_bundled_programs() {
+        let bash = wrapper("bash", "deploy").expect("bash is supported");
+        assert!(bash.contains("source <(INKJET_COMPLETE=bash deploy)"));
+        let zsh = wrapper("zsh", "deploy").expect("zsh is supported");
+        assert!(zsh.starts_with("#compdef deploy\n"));
+        assert!(zsh.contains("_clap_dynamic_completer_deploy \"$@\""));
+        assert!(wrapper("tcsh", "inkjet").is_none());
+    }
+
+    #[test]
+    fn escapes_hyphenated_names_for_zsh() {
+        let zsh = wrapper("zsh", "my-tool").expect("zsh is supported");
+        assert!(zsh.starts_with("#compdef my-tool\n"));
+        assert!(zsh.contains("source <(INKJET_COMPLETE=zsh my-tool)"));
+        assert!(zsh.contains("_clap_dynamic_completer_my_tool \"$@\""));
+        let nu = wrapper("nushell", "my-tool").expect("nushell is supported");
+        assert!(nu.contains("$spans.0 == \"my-tool\""));
+        assert!(!nu.contains('@'));
+    }
+}
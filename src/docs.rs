@@ -0,0 +1,259 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use pulldown_cmark::{Options, Parser, html};
+
+use crate::command::{Arg, CommandBlock, NamedFlag};
+use crate::inkfile::Inkfile;
+
+/// Renders a markdown reference of every command in the inkfile with its args, flags, choices and defaults.
+/// program is the name the commands are called with, i.e. `inkjet` or the `name` directive.
+pub fn markdown(inkfile: &Inkfile, program: &str) -> String {
+    let mut out = format!("# {program}\n\n");
+    let about = inkfile.config.about.as_ref().unwrap_or(&inkfile.root.desc);
+    if !about.trim().is_empty() {
+        out.push_str(&format!("{}\n\n", about.trim()));
+    }
+    if let Some(version) = &inkfile.config.version {
+        out.push_str(&format!("Version {version}\n\n"));
+    }
+
+    let mut commands = vec![];
+    collect(&inkfile.root.subcommands, program, &mut commands);
+    if commands.is_empty() {
+        return out;
+    }
+    out.push_str("## Commands\n\n");
+    for (path, cmd) in &commands {
+        let summary = cmd.desc.lines().next().unwrap_or_default();
+        out.push_str(&format!("- [`{path}`](#{}) {summary}\n", anchor(path)));
+    }
+    out.push('\n');
+    for (path, cmd) in &commands {
+        out.push_str(&command_section(path, cmd));
+    }
+    out
+}
+
+/// Renders the markdown reference as a standalone HTML page.
+pub fn html(inkfile: &Inkfile, program: &str) -> String {
+    let markdown = markdown(inkfile, program);
+    let mut body = String::new();
+    html::push_html(
+        &mut body,
+        Parser::new_ext(&markdown, Options::ENABLE_TABLES),
+    );
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape_html(program)
+    )
+}
+
+/// Collects the visible commands depth first with their full path, i.e. `inkjet services start`.
+/// Commands starting with an underscore are hidden from help, so they are skipped here too.
+fn collect<'a>(
+    subcommands: &'a [CommandBlock],
+    parent: &str,
+    commands: &mut Vec<(String, &'a CommandBlock)>,
+) {
+    for cmd in subcommands.iter().filter(|c| !c.name.starts_with('_')) {
+        let path = format!("{parent} {}", cmd.name);
+        commands.push((path.clone(), cmd));
+        collect(&cmd.subcommands, &path, commands);
+    }
+}
+
+fn command_section(path: &str, cmd: &CommandBlock) -> String {
+    let mut out = format!("## {path}\n\n");
+    if !cmd.desc.trim().is_empty() {
+        out.push_str(&format!("{}\n\n", cmd.desc.trim()));
+    }
+    out.push_str(&format!("```\n{}\n```\n\n", usage(path, cmd)));
+    let aliases: Vec<String> = cmd
+        .aliases
+        .split("//")
+        .filter(|a| !a.is_empty())
+        .map(|a| format!("`{a}`"))
+        .collect();
+    if !aliases.is_empty() {
+        out.push_str(&format!("Aliases: {}\n\n", aliases.join(", ")));
+    }
+    if !cmd.args.is_empty() {
+        out.push_str("| Argument | Required | Default |\n| --- | --- | --- |\n");
+        for arg in &cmd.args {
+            out.push_str(&format!(
+                "| `{}` | {} | {} |\n",
+                arg_placeholder(arg),
+                yes_no(arg.required),
+                arg.default
+                    .as_ref()
+                    .map(|d| format!("`{}`", cell(d)))
+                    .unwrap_or_default()
+            ));
+        }
+        out.push('\n');
+    }
+    if !cmd.named_flags.is_empty() {
+        out.push_str(
+            "| Flag | Type | Required | Choices | Description |\n| --- | --- | --- | --- | --- |\n",
+        );
+        for flag in &cmd.named_flags {
+            let choices: Vec<String> = flag.choices.iter().map(|c| format!("`{c}`")).collect();
+            out.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                flag_names(flag),
+                flag_type(flag),
+                yes_no(flag.required),
+                choices.join(", "),
+                cell(&flag.desc)
+            ));
+        }
+        out.push('\n');
+    }
+    if !cmd.subcommands.is_empty() && !cmd.script.has_script() {
+        let names: Vec<String> = cmd
+            .subcommands
+            .iter()
+            .filter(|c| !c.name.starts_with('_'))
+            .map(|c| format!("[`{}`](#{})", c.name, anchor(&format!("{path} {}", c.name))))
+            .collect();
+        out.push_str(&format!("Subcommands: {}\n\n", names.join(", ")));
+    }
+    out
+}
+
+/// Returns the usage line for a command, i.e. `inkjet greet [OPTIONS] <name> [greeting]`
+fn usage(path: &str, cmd: &CommandBlock) -> String {
+    let mut usage = path.to_string();
+    if !cmd.named_flags.is_empty() {
+        usage.push_str(" [OPTIONS]");
+    }
+    for arg in &cmd.args {
+        if arg.last {
+            usage.push_str(" --");
+        }
+        usage.push(' ');
+        usage.push_str(&arg_placeholder(arg));
+    }
+    if !cmd.subcommands.is_empty() {
+        usage.push_str(" <COMMAND>");
+    }
+    usage
+}
+
+fn arg_placeholder(arg: &Arg) -> String {
+    let dots = if arg.multiple { "..." } else { "" };
+    if arg.required {
+        format!("<{}>{dots}", arg.name)
+    } else {
+        format!("[{}]{dots}", arg.name)
+    }
+}
+
+fn flag_names(flag: &NamedFlag) -> String {
+    let value = if flag.takes_value {
+        format!(" <{}>", flag.name)
+    } else {
+        "".to_string()
+    };
+    match (flag.short.is_empty(), flag.long.is_empty()) {
+        (false, false) => format!("`-{}`, `--{}{value}`", flag.short, flag.long),
+        (false, true) => format!("`-{}{value}`", flag.short),
+        _ => format!("`--{}{value}`", flag.long),
+    }
+}
+
+fn flag_type(flag: &NamedFlag) -> String {
+    let kind = if !flag.takes_value {
+        "bool"
+    } else if flag.validate_as_number {
+        "number"
+    } else {
+        "string"
+    };
+    if flag.multiple {
+        format!("{kind} (multiple)")
+    } else {
+        kind.to_string()
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+/// Keeps a value on one line of a markdown table
+fn cell(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+/// Returns the heading anchor GitHub generates for a heading
+fn anchor(heading: &str) -> String {
+    heading
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_commands_args_and_flags() {
+        let inkfile = Inkfile::parse(
+            r#"
+# tasks
+
+> Project tasks
+
+## greet//hi (name) (greeting=Hello)
+
+> Greet someone
+
+**OPTIONS**
+
+- flag: --times |number| How many times
+- flag: -l --loud |bool| Shout it
+
+```sh
+echo "$greeting $name"
+```
+
+## _internal
+
+```sh
+echo hidden
+```
+"#,
+        )
+        .expect("valid inkfile");
+        let docs = markdown(&inkfile, "tool");
+        assert!(docs.starts_with("# tool\n\nProject tasks\n"));
+        assert!(docs.contains("- [`tool greet`](#tool-greet) Greet someone\n"));
+        assert!(docs.contains("```\ntool greet [OPTIONS] <name> [greeting]\n```"));
+        assert!(docs.contains("Aliases: `hi`"));
+        assert!(docs.contains("| `[greeting]` | no | `Hello` |"));
+        assert!(docs.contains("| `--times <times>` | number | no |  | How many times |"));
+        assert!(docs.contains("| `-l`, `--loud` | bool | no |  | Shout it |"));
+        assert!(!docs.contains("_internal"));
+
+        let page = html(&inkfile, "tool");
+        assert!(page.contains("<title>tool</title>"));
+        assert!(page.contains("<table>"));
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use std::fmt;
+use std::io;
+
+use crate::parser::ParseError;
+
+/// Error is returned by the library API instead of being printed.
+#[derive(Debug)]
+pub enum Error {
+    /// The inkfile could not be read or written
+    Io {
+        /// The path of the inkfile
+        path: String,
+        /// The underlying I/O error
+        source: io::Error,
+    },
+    /// The inkfile or one of its imports is invalid
+    Parse(ParseError),
+    /// No command matches the given path
+    CommandNotFound(String),
+    /// The command has subcommands but no code block to run
+    NoScript(String),
+    /// The inkfile that defines a command is not one of the sources of the Inkfile
+    SourceNotFound {
+        /// The name of the command
+        command: String,
+        /// The path of the inkfile that defines it
+        path: String,
+    },
+    /// A required arg was not supplied
+    MissingArg {
+        /// The name of the command
+        command: String,
+        /// The name of the arg
+        arg: String,
+    },
+    /// More positional values were supplied than the command accepts
+    UnexpectedArg {
+        /// The name of the command
+        command: String,
+        /// The first value that was not consumed
+        value: String,
+    },
+    /// The flag is not defined for the command
+    UnknownFlag {
+        /// The name of the command
+        command: String,
+        /// The flag as it was supplied
+        flag: String,
+    },
+    /// A required flag was not supplied
+    MissingFlag {
+        /// The name of the command
+        command: String,
+        /// The name of the flag
+        flag: String,
+    },
+    /// A flag value is not one of its choices or is not a number
+    InvalidFlagValue {
+        /// The name of the flag
+        flag: String,
+        /// Why the value was rejected
+        message: String,
+    },
+    /// The interpreter for the script could not be started
+    Spawn {
+        /// The program that was run
+        program: String,
+        /// The underlying I/O error
+        source: io::Error,
+    },
+    /// The file written for a shebang script could not be deleted after it ran
+    TempFile {
+        /// The path of the file
+        path: String,
+        /// The underlying I/O error
+        source: io::Error,
+    },
+}
+
+impl Error {
+    /// Returns the exit code the inkjet CLI uses for this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 66,
+            Error::Parse(_) => 78,
+            Error::Spawn { .. } | Error::TempFile { .. } => 5,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "Error reading inkfile {path}: {source}"),
+            Error::Parse(err) => write!(f, "{err}"),
+            Error::CommandNotFound(path) => write!(f, "No command named \"{path}\""),
+            Error::NoScript(name) => write!(f, "Command \"{name}\" has no script to run"),
+            Error::SourceNotFound { command, path } => {
+                write!(
+                    f,
+                    "{command}: the inkfile {path} that defines it is not loaded"
+                )
+            }
+            Error::MissingArg { command, arg } => {
+                write!(f, "{command}: the required arg \"{arg}\" was not supplied")
+            }
+            Error::UnexpectedArg { command, value } => {
+                write!(f, "{command}: unexpected argument \"{value}\"")
+            }
+            Error::UnknownFlag { command, flag } => {
+                write!(f, "{command}: unknown flag \"{flag}\"")
+            }
+            Error::MissingFlag { command, flag } => {
+                write!(
+                    f,
+                    "{command}: the required flag \"{flag}\" was not supplied"
+                )
+            }
+            Error::InvalidFlagValue { message, .. } => write!(f, "{message}"),
+            Error::Spawn { program, source } => {
+                write!(f, "Unable to run {program}: {source}")
+            }
+            Error::TempFile { path, source } => {
+                write!(f, "Failed to delete temporary file {path}: {source}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. }
+            | Error::Spawn { source, .. }
+            | Error::TempFile { source, .. } => Some(source),
+            Error::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
@@ -1,23 +1,29 @@
 // Copyright 2020 Brandon Kalinowski (brandonkal)
 // SPDX-License-Identifier: MIT
 
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::collections::HashMap;
+use std::env;
 use std::io;
-use std::io::Write;
+use std::io::{BufRead, BufReader, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process;
-use std::{env, fs};
-use walkdir::WalkDir;
+use std::sync::{Arc, Mutex, mpsc};
+use std::{thread, time};
 
-use crate::command::CommandBlock;
-use crate::utils;
+use regex::{Captures, Regex};
 
-/// takes a source string and generates a temporary hash for the filename.
-fn hash_source(s: &str) -> String {
-    let mut hasher = DefaultHasher::new();
-    s.hash(&mut hasher);
-    format!("{:x}", hasher.finish())
+use crate::command::{CommandBlock, Script};
+use crate::error::Error;
+use crate::inkfile::Inkfile;
+use crate::{runner, utils};
+
+/// takes a source string and generates a hash for a filename.
+/// It is a fixed FNV-1a hash, so files named by it are found again by other builds of inkjet.
+pub(crate) fn hash_source(s: &str) -> String {
+    let hash = s.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:x}")
 }
 
 /// we append  `set -e` to these shells as a sensible default
@@ -25,48 +31,6 @@ fn needs_set_e(s: &str) -> bool {
     s == "sh" || s == "bash" || s.is_empty() || s == "dash" || s == "zsh"
 }
 
-/// Executes a shell function that finds all inkjet.md files in a directory and
-/// merges them together. Useful for projects with several inkjet.md files.
-/// returns the output of the merge operation: a new inkfile content String
-pub fn execute_merge_command(inkfile_path: &str) -> Result<String, String> {
-    let parent_dir = get_parent_dir(inkfile_path);
-    // Collect paths that match the criteria
-    let mut inkjet_files: Vec<PathBuf> = vec![];
-
-    // Traverse the directory and find matching files
-    for entry in WalkDir::new(parent_dir) {
-        match entry {
-            Ok(path) => {
-                let filename = path.file_name().to_string_lossy();
-
-                if filename == "inkjet.md" || filename.ends_with(".inkjet.md") {
-                    inkjet_files.push(path.into_path());
-                }
-            }
-            _ => continue,
-        }
-    }
-
-    // Sort files by the number of directories in their path
-    inkjet_files.sort_by_key(|path| path.components().count());
-
-    // Prepare a String to collect the combined text
-    let mut combined_text = String::new();
-
-    // Append the content of each file with the required format
-    for file in inkjet_files {
-        let file_str = file.to_string_lossy();
-        combined_text.push_str(&format!("<!-- inkfile: {file_str} -->\n"));
-
-        match fs::read_to_string(&file) {
-            Ok(content) => combined_text.push_str(&content),
-            Err(e) => return Err(format!("Error reading file {file_str}: {e}")),
-        }
-    }
-
-    Ok(combined_text)
-}
-
 fn run_bat(source: String, lang: &str) -> io::Result<process::Child> {
     match process::Command::new("bat")
         .args(["--plain", "--language", lang])
@@ -85,6 +49,16 @@ fn run_bat(source: String, lang: &str) -> io::Result<process::Child> {
     }
 }
 
+/// Outcome is the result of `execute_command`
+#[derive(Debug)]
+pub enum Outcome {
+    /// The script ran and exited with this status
+    Exited(process::ExitStatus),
+    /// The script was printed instead of being run.
+    /// Holds the exit status of `bat` if it was used to highlight the script.
+    Previewed(Option<process::ExitStatus>),
+}
+
 /// Execute a given command using its executor or sh. If preview is set, the script will be printed instead.
 pub fn execute_command(
     mut cmd: CommandBlock,
@@ -92,15 +66,13 @@ pub fn execute_command(
     preview: bool,
     color: bool,
     fixed_dir: bool,
-) -> Option<io::Result<process::ExitStatus>> {
+) -> io::Result<Outcome> {
     if cmd.script.source.is_empty() {
         let msg = "CommandBlock has no script."; // cov:include (unusual)
-        return Some(Err(io::Error::other(msg))); // cov:include
+        return Err(io::Error::other(msg)); // cov:include
     }
 
-    if cmd.script.executor.is_empty() && !cmd.script.source.trim().starts_with("#!") {
-        cmd.script.executor = String::from("sh"); // default to default shell
-    }
+    cmd.script.executor = language_of(&cmd.script).to_string();
     let source = if needs_set_e(&cmd.script.executor) {
         format!("set -e\n{}", &cmd.script.source)
     } else {
@@ -110,163 +82,769 @@ pub fn execute_command(
     if preview {
         if !color {
             print!("{source}");
-            return None;
+            return Ok(Outcome::Previewed(None));
         }
         match run_bat(source.clone(), &cmd.script.executor) {
-            Ok(mut child) => Some(child.wait()),
+            Ok(mut child) => Ok(Outcome::Previewed(Some(child.wait()?))),
             Err(_) => {
                 print!("{source}"); // cov:include (bat exists)
-                None // cov:include
+                Ok(Outcome::Previewed(None)) // cov:include
             }
         }
     } else {
-        let mut local_inkfile = cmd.inkjet_file.trim();
-        if local_inkfile.is_empty() {
-            local_inkfile = inkfile_path
-        }
-        let parent_dir = get_parent_dir(local_inkfile);
-        let mut tempfile = String::new();
-        let (mut child, mut executor) = prepare_command(&cmd, &parent_dir, &mut tempfile);
-        child = add_utility_variables(child, inkfile_path, local_inkfile);
-        child = add_flag_variables(child, &cmd);
-        if fixed_dir {
-            child.current_dir(parent_dir);
+        let local_inkfile = local_inkfile(&cmd, inkfile_path);
+        let registry = ExecutorRegistry::new();
+        let mut prepared = build_child(&cmd, inkfile_path, fixed_dir, &registry)?;
+        // When stderr is captured, error locations are rewritten to point into the inkfile
+        let capture_stderr = !io::stderr().is_terminal();
+        if capture_stderr {
+            prepared.command.stderr(process::Stdio::piped());
         }
-        let spawned_child = child.spawn();
+        let spawned_child = prepared.command.spawn();
         match spawned_child {
             Err(err) => {
                 if err.kind() == io::ErrorKind::NotFound {
-                    if executor.is_empty() {
-                        executor = String::from("the executor")
-                    }
                     eprintln!(
                         "{} Please check if {} is installed to run the command.",
-                        utils::ERROR_MSG,
-                        executor
+                        utils::error_msg(),
+                        prepared.program
                     );
                 }
-                delete_file(&tempfile); // cov:include (unusual)
-                Some(io::Result::Err(err)) // cov:include
+                let _ = prepared.delete_tempfile(); // cov:include (unusual)
+                Err(err) // cov:include
             }
             Ok(mut child) => {
+                let mapper = LineMapper::new(&cmd, &display_path(local_inkfile), &prepared);
+                let file = mapper.file.clone();
+                let forwarder = child
+                    .stderr
+                    .take()
+                    .map(|stderr| forward_stderr(stderr, mapper));
                 let r = child.wait();
-                delete_file(&tempfile);
-                Some(r)
+                let deleted = prepared.delete_tempfile();
+                let status = r?;
+                deleted.map_err(io::Error::other)?;
+                let error_line = forwarder.and_then(StderrForwarder::finish);
+                report_failure(&cmd, &file, status, error_line, color);
+                Ok(Outcome::Exited(status))
             }
         }
     }
 }
 
-fn delete_file(file: &str) {
-    if !file.is_empty() && std::fs::remove_file(file).is_err() {
-        eprintln!(
-            "{} Failed to delete temporary file {}",
-            utils::ERROR_MSG,
-            file
-        ); // cov:ignore (unusual)
+/// How long to wait for the rest of a command's stderr after it exits.
+/// A background job can keep stderr open, so inkjet does not wait for it to close.
+const STDERR_DRAIN_TIMEOUT: time::Duration = time::Duration::from_millis(200);
+
+/// StderrForwarder copies the stderr of a command to inkjet's stderr on a thread
+struct StderrForwarder {
+    /// The last inkfile line named in stderr. Only this is kept, so a chatty script costs no memory.
+    error_line: Arc<Mutex<Option<usize>>>,
+    done: mpsc::Receiver<()>,
+}
+
+impl StderrForwarder {
+    /// Waits a moment for the remaining output and returns the last inkfile line named in it
+    fn finish(self) -> Option<usize> {
+        let _ = self.done.recv_timeout(STDERR_DRAIN_TIMEOUT);
+        *self.error_line.lock().unwrap_or_else(|e| e.into_inner())
     }
 }
 
-/// `prepare_command` takes a CommandBlock struct and builds a `process::Command` that can then be executed as a child process.
-fn prepare_command(
-    cmd: &CommandBlock,
-    parent_dir: &str,
-    tempfile: &mut String,
-) -> (process::Command, String) {
-    let mut executor = cmd.script.executor.clone();
-    let source = cmd.script.source.trim();
-    if source.starts_with("#!") {
-        let hash = hash_source(source);
-        *tempfile = format!("{parent_dir}/.inkjet-order.{hash}");
-        std::fs::write(&tempfile, source)
-            .unwrap_or_else(|_| panic!("Inkjet: Unable to write file {}", &tempfile));
-
-        #[cfg(not(windows))]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let meta =
-                std::fs::metadata(&tempfile).expect("Inkjet: Unable to read file permissions");
-            let mut perms = meta.permissions();
-            perms.set_mode(0o775);
-            std::fs::set_permissions(&tempfile, perms).expect("Inkjet: Could not set permissions");
+/// Streams stderr line by line as it arrives, rewriting error locations to point into the inkfile
+fn forward_stderr(stderr: process::ChildStderr, mapper: LineMapper) -> StderrForwarder {
+    let error_line = Arc::new(Mutex::new(None));
+    let (done_tx, done) = mpsc::channel();
+    let last = Arc::clone(&error_line);
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stderr);
+        let mut buf = vec![];
+        while let Ok(n) = reader.read_until(b'\n', &mut buf) {
+            if n == 0 {
+                break;
+            }
+            let (line, md_line) = mapper.rewrite(&String::from_utf8_lossy(&buf));
+            if md_line.is_some() {
+                *last.lock().unwrap_or_else(|e| e.into_inner()) = md_line;
+            }
+            eprint!("{line}");
+            buf.clear();
         }
+        let _ = done_tx.send(());
+    });
+    StderrForwarder { error_line, done }
+}
 
-        (
-            process::Command::new(tempfile),
-            String::from("the executor"),
-        )
+/// Prints where a command that exited unsuccessfully is defined, i.e. `inkjet.md:12: command "build" exited with status 1`
+/// If the interpreter named a line of the script in its errors, the last such line is added.
+pub(crate) fn report_failure(
+    cmd: &CommandBlock,
+    file: &str,
+    status: process::ExitStatus,
+    error_line: Option<usize>,
+    color: bool,
+) {
+    if status.success() {
+        return;
+    }
+    let prefix = if color {
+        utils::error_msg()
     } else {
-        match executor.as_ref() {
-            "js" | "javascript" => {
-                let mut child;
-                child = process::Command::new("node");
-                child.arg("-e").arg(source);
-                (child, String::from("node"))
+        utils::plain_msg("ERROR")
+    };
+    let code = status
+        .code()
+        .map_or("a signal".to_string(), |c| format!("status {c}"));
+    let error_line = error_line.map_or("".to_string(), |l| format!(" (last error at line {l})"));
+    eprintln!(
+        "{prefix} {file}:{}: command \"{}\" exited with {code}{error_line}",
+        cmd.script.line, cmd.name
+    );
+}
+
+/// Returns the inkfile a CommandBlock was defined in. This differs from the root inkfile for imported commands.
+pub(crate) fn local_inkfile<'a>(cmd: &'a CommandBlock, inkfile_path: &'a str) -> &'a str {
+    let local_inkfile = cmd.inkjet_file.trim();
+    if local_inkfile.is_empty() {
+        inkfile_path
+    } else {
+        local_inkfile
+    }
+}
+
+/// Returns the language code a script runs as. A code block without one runs with sh unless it starts with a shebang.
+/// Both the CLI and ExecutionRequest resolve it here, so they pick the same Executor.
+fn language_of(script: &Script) -> &str {
+    if script.executor.is_empty() && !script.source.trim().starts_with("#!") {
+        "sh"
+    } else {
+        &script.executor
+    }
+}
+
+/// Builds the child process for a CommandBlock with the inkjet and flag environment variables set.
+pub(crate) fn build_child(
+    cmd: &CommandBlock,
+    inkfile_path: &str,
+    fixed_dir: bool,
+    registry: &ExecutorRegistry,
+) -> io::Result<PreparedCommand> {
+    let local_inkfile = local_inkfile(cmd, inkfile_path);
+    let parent_dir = get_parent_dir(local_inkfile);
+    let ctx = ExecContext {
+        language: language_of(&cmd.script),
+        dir: Path::new(&parent_dir),
+        command: &cmd.name,
+    };
+    let mut prepared = registry.prepare(&cmd.script, &ctx)?;
+    add_utility_variables(&mut prepared.command, inkfile_path, local_inkfile);
+    add_flag_variables(&mut prepared.command, cmd);
+    if fixed_dir {
+        prepared.command.current_dir(parent_dir);
+    }
+    Ok(prepared)
+}
+
+/// Runs the completion script of an arg or flag in the environment of its command.
+/// Each line of output is a candidate value. A tab separates a value from its description.
+/// A script that fails or cannot be started has no candidates.
+pub(crate) fn completion_candidates(
+    cmd: &CommandBlock,
+    script: &Script,
+    inkfile_path: &str,
+    fixed_dir: bool,
+) -> Vec<(String, Option<String>)> {
+    let mut cmd = cmd.clone();
+    cmd.script = script.clone();
+    let Ok(mut prepared) = build_child(&cmd, inkfile_path, fixed_dir, &ExecutorRegistry::new())
+    else {
+        return vec![];
+    };
+    let output = prepared
+        .command
+        .stdin(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .output();
+    // Completions must not print, and a leftover file does not change the candidates
+    let _ = prepared.delete_tempfile();
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| match line.split_once('\t') {
+                Some((value, desc)) => (value.to_string(), Some(desc.to_string())),
+                None => (line.to_string(), None),
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// ExecutionRequest runs a command of an Inkfile without going through the CLI.
+/// Values are validated the same way the CLI validates them, and problems are returned as an Error.
+#[derive(Debug, Clone)]
+pub struct ExecutionRequest<'a> {
+    inkfile: &'a Inkfile,
+    cmd: CommandBlock,
+    args: Vec<String>,
+    flags: Vec<(String, String)>,
+    cwd: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    capture: bool,
+    registry: Option<&'a ExecutorRegistry>,
+}
+
+/// ExecutionResult is the exit status and output of a command run by an ExecutionRequest
+#[derive(Debug, Clone)]
+pub struct ExecutionResult {
+    /// The exit status of the script
+    pub status: process::ExitStatus,
+    /// The captured standard output. It is empty if output was not captured.
+    pub stdout: String,
+    /// The captured standard error. It is empty if output was not captured.
+    pub stderr: String,
+}
+
+impl ExecutionResult {
+    /// Returns true if the script exited successfully
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+    /// Returns the exit code of the script. It is None if the script was killed by a signal.
+    pub fn code(&self) -> Option<i32> {
+        self.status.code()
+    }
+}
+
+impl<'a> ExecutionRequest<'a> {
+    /// Creates a request for the command at a space separated path, i.e. `services start`.
+    /// Output is captured by default.
+    pub fn new(inkfile: &'a Inkfile, command: &str) -> Result<Self, Error> {
+        let cmd = inkfile
+            .find(command)
+            .filter(|c| c.cmd_level > inkfile.root.cmd_level)
+            .ok_or_else(|| Error::CommandNotFound(command.to_string()))?;
+        Ok(ExecutionRequest {
+            inkfile,
+            cmd: cmd.clone(),
+            args: vec![],
+            flags: vec![],
+            cwd: None,
+            env: vec![],
+            capture: true,
+            registry: None,
+        })
+    }
+
+    #[must_use]
+    /// Adds a positional value. Values are assigned to the command's args in order.
+    pub fn arg(mut self, value: impl Into<String>) -> Self {
+        self.args.push(value.into());
+        self
+    }
+
+    #[must_use]
+    /// Sets a named flag by its name, long or short name. Boolean flags are set with a value of `true`.
+    /// Flags that accept multiple values can be set more than once.
+    pub fn flag(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.flags.push((name.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    /// Runs the script in dir instead of the inkfile's directory
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    #[must_use]
+    /// Sets an environment variable for the script
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    /// Captures stdout and stderr into the ExecutionResult. Otherwise they are inherited from this process.
+    pub fn capture_output(mut self, capture: bool) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    #[must_use]
+    /// Runs the script with the executors of registry instead of the built-in executors
+    pub fn executors(mut self, registry: &'a ExecutorRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Validates the supplied values and runs the script
+    pub fn run(self) -> Result<ExecutionResult, Error> {
+        let cmd = self.apply_values()?;
+        if !cmd.script.has_script() {
+            return Err(Error::NoScript(cmd.name));
+        }
+        let builtins;
+        let registry = match self.registry {
+            Some(registry) => registry,
+            None => {
+                builtins = ExecutorRegistry::new();
+                &builtins
             }
-            "py" | "python" | "python3" => {
-                #[cfg(not(windows))]
-                let the_executor = "python3";
+        };
+        let spawn_err = |source| Error::Spawn {
+            program: cmd.script.executor.clone(),
+            source,
+        };
+        let mut prepared = build_child(
+            &cmd,
+            &self.inkfile.path,
+            self.inkfile.config.fixed_dir,
+            registry,
+        )
+        .map_err(spawn_err)?;
+        let child = &mut prepared.command;
+        if let Some(cwd) = &self.cwd {
+            child.current_dir(cwd);
+        }
+        child.envs(self.env);
+        let result = if self.capture {
+            child.output().map(|output| ExecutionResult {
+                status: output.status,
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            })
+        } else {
+            child.status().map(|status| ExecutionResult {
+                status,
+                stdout: String::new(),
+                stderr: String::new(),
+            })
+        };
+        let deleted = prepared.delete_tempfile();
+        let result = result.map_err(|source| Error::Spawn {
+            program: prepared.program.clone(),
+            source,
+        })?;
+        deleted?;
+        Ok(result)
+    }
 
-                #[cfg(windows)]
-                let the_executor = "python";
+    /// Returns the CommandBlock with the supplied values applied to its args and flags
+    fn apply_values(&self) -> Result<CommandBlock, Error> {
+        let mut cmd = self.cmd.clone();
+        let name = cmd.name.clone();
+        let mut values = self.args.iter();
+        for arg in &mut cmd.args {
+            arg.val = if arg.multiple {
+                values.by_ref().cloned().collect::<Vec<_>>().join(" ")
+            } else {
+                values.next().cloned().unwrap_or_default()
+            };
+            if arg.val.is_empty() && arg.required && arg.default.is_none() {
+                return Err(Error::MissingArg {
+                    command: name,
+                    arg: arg.name.clone(),
+                });
+            }
+        }
+        if let Some(value) = values.next() {
+            return Err(Error::UnexpectedArg {
+                command: name,
+                value: value.clone(),
+            });
+        }
 
-                let mut child = process::Command::new(the_executor);
-                child.arg("-c").arg(source);
-                (child, String::from(the_executor))
+        for (key, value) in &self.flags {
+            let key = key.trim_start_matches('-');
+            let flag = cmd
+                .named_flags
+                .iter_mut()
+                .find(|f| f.name == key || f.long == key || f.short == key)
+                .ok_or_else(|| Error::UnknownFlag {
+                    command: name.clone(),
+                    flag: key.to_string(),
+                })?;
+            if !flag.takes_value {
+                flag.val = if value == "true" {
+                    value.clone()
+                } else {
+                    String::new()
+                };
+                continue;
             }
-            "rb" | "ruby" => {
-                let mut child = process::Command::new("ruby");
-                child.arg("-e").arg(source);
-                (child, String::from("ruby"))
+            if !flag.choices.is_empty() && !flag.choices.contains(value) {
+                return Err(Error::InvalidFlagValue {
+                    flag: flag.name.clone(),
+                    message: format!("{} flag expects one of {:?}", flag.name, flag.choices),
+                });
             }
-            "php" => {
-                let mut child = process::Command::new("php");
-                child.arg("-r").arg(source);
-                (child, String::from("php"))
+            if runner::is_invalid_number(flag.validate_as_number, value) {
+                return Err(Error::InvalidFlagValue {
+                    flag: flag.name.clone(),
+                    message: runner::not_number_err_msg(&flag.name),
+                });
             }
-            "ts" | "typescript" => {
-                let mut child = process::Command::new("deno");
-                child.arg("eval").arg("--ext=ts").arg(source);
-                (child, String::from("deno"))
+            if flag.multiple && !flag.val.is_empty() {
+                flag.val = format!("{} {value}", flag.val);
+            } else {
+                flag.val = value.clone();
             }
-            "go" => {
-                let mut child = process::Command::new("yaegi");
-                child.arg("-e").arg(source);
-                (child, String::from("yaegi"))
+        }
+        if let Some(flag) = cmd
+            .named_flags
+            .iter()
+            .find(|f| f.required && f.val.is_empty())
+        {
+            return Err(Error::MissingFlag {
+                command: name,
+                flag: flag.name.clone(),
+            });
+        }
+        Ok(cmd)
+    }
+}
+
+/// Returns the path relative to the current directory if it is below it
+pub(crate) fn display_path(path: &str) -> String {
+    let relative = env::current_dir().ok().and_then(|dir| {
+        Path::new(path)
+            .strip_prefix(dir)
+            .ok()
+            .map(|p| p.to_path_buf())
+    });
+    match relative {
+        Some(p) => p.to_string_lossy().to_string(),
+        None => path.to_string(),
+    }
+}
+
+/// LineMapper rewrites line numbers that an interpreter reports for a script into lines of the inkfile.
+/// Shells report lines of the script passed with `-c`, which starts with the injected `set -e`.
+struct LineMapper {
+    file: String,
+    /// The inkfile line of the first line that is passed to the interpreter
+    first_line: usize,
+    /// The number of lines inkjet adds to the top of the script
+    injected: usize,
+    patterns: Vec<(Regex, &'static str)>,
+}
+
+impl LineMapper {
+    fn new(cmd: &CommandBlock, file: &str, prepared: &PreparedCommand) -> LineMapper {
+        // The source is trimmed before it is run, so leading blank lines are not counted by the interpreter
+        let leading = cmd
+            .script
+            .source
+            .lines()
+            .take_while(|l| l.trim().is_empty())
+            .count();
+        let mut names = vec![regex::escape(&prepared.program)];
+        if let Some(tempfile) = &prepared.tempfile {
+            names.push(regex::escape(&tempfile.to_string_lossy()));
+        }
+        let names = names.join("|");
+        let pattern = |p: &str| Regex::new(p).expect("Inkjet: invalid line pattern");
+        let patterns = vec![
+            // sh: 3: foo: not found | bash: line 3: foo: command not found
+            (
+                pattern(&format!(r"^(?:{names}): (?:line )?(?P<n>\d+):")),
+                "{file}:{line}:",
+            ),
+            // File "<string>", line 3, in <module>
+            (
+                pattern(&format!(r#""(?:<string>|{names})", line (?P<n>\d+)"#)),
+                "\"{file}\", line {line}",
+            ),
+            // [eval]:3
+            (
+                pattern(&format!(r"(?:\[eval\]|{names}):(?P<n>\d+)")),
+                "{file}:{line}",
+            ),
+            // -e:3: undefined local variable or method
+            (
+                pattern(&format!(r"^(?:-e|{names}):(?P<n>\d+):")),
+                "{file}:{line}:",
+            ),
+        ];
+        LineMapper {
+            file: file.to_string(),
+            first_line: cmd.script.line + leading,
+            injected: prepared.injected_lines,
+            patterns,
+        }
+    }
+
+    /// Maps a line of the script the interpreter ran to a line of the inkfile
+    fn markdown_line(&self, script_line: usize) -> Option<usize> {
+        if self.first_line == 0 || script_line <= self.injected {
+            return None;
+        }
+        Some(self.first_line + script_line - 1 - self.injected)
+    }
+
+    /// Rewrites the first known `line N` pattern of a line of stderr.
+    /// The inkfile line it now points at is returned with it.
+    fn rewrite(&self, line: &str) -> (String, Option<usize>) {
+        for (pattern, template) in &self.patterns {
+            let mut matched = None;
+            let rewritten = pattern.replacen(line, 1, |caps: &Captures| {
+                let script_line = caps["n"].parse().unwrap_or(0);
+                match self.markdown_line(script_line) {
+                    Some(md_line) => {
+                        matched = Some(md_line);
+                        template
+                            .replace("{file}", &self.file)
+                            .replace("{line}", &md_line.to_string())
+                    }
+                    None => caps[0].to_string(),
+                }
+            });
+            if matched.is_some() {
+                return (rewritten.into_owned(), matched);
             }
-            // If no language is specified, we use the default shell
-            "" | "sh" | "bash" | "zsh" | "dash" => {
-                if executor.is_empty() {
-                    executor = "sh".to_string() // cov:ignore (already added by execute_command)
+        }
+        (line.to_string(), None)
+    }
+}
+
+/// Interpreter is the program that runs a CommandBlock's script. It is resolved from the language code or a shebang.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Interpreter {
+    /// The script starts with a shebang. It is written to a file and executed directly.
+    Shebang {
+        /// The shebang line without the leading `#!`, i.e. `/usr/bin/env python3`
+        line: String,
+    },
+    /// A shell that runs the script with `-c`. `set -e` is added to the top of the script.
+    Shell {
+        /// The shell program, i.e. sh, bash, zsh or dash
+        program: String,
+    },
+    /// A program that accepts the script as an argument following `args`
+    Inline {
+        /// The interpreter program, i.e. node or python3
+        program: String,
+        /// The arguments before the script, i.e. `-e`
+        args: Vec<String>,
+    },
+}
+
+impl Interpreter {
+    /// The name of the program that runs the script. For shebangs, this is the program named by the shebang line.
+    pub fn program(&self) -> String {
+        match self {
+            Interpreter::Shebang { line } => {
+                let mut words = line.split_whitespace();
+                let first = words.next().unwrap_or("");
+                let name = Path::new(first)
+                    .file_name()
+                    .map_or(first.to_string(), |n| n.to_string_lossy().to_string());
+                // `#!/usr/bin/env -S deno run` names deno
+                if name == "env" {
+                    words
+                        .find(|w| !w.starts_with('-'))
+                        .unwrap_or("env")
+                        .to_string()
+                } else {
+                    name
                 }
-                let mut child = process::Command::new(&executor);
-                let top = "set -e"; // a sane default for scripts
-                let src = format!("{top}\n{source}");
-                child.arg("-c").arg(src);
-                (child, executor)
             }
+            Interpreter::Shell { program } | Interpreter::Inline { program, .. } => program.clone(),
+        }
+    }
+}
+
+/// Resolves the Interpreter for a script from its executor (the language code of the code block).
+/// A shebang always takes precedence over the language code.
+pub fn resolve_interpreter(executor: &str, source: &str) -> Interpreter {
+    let source = source.trim();
+    if let Some(line) = source.strip_prefix("#!") {
+        return Interpreter::Shebang {
+            line: line.lines().next().unwrap_or("").trim().to_string(),
+        };
+    }
+    let inline = |program: &str, args: &[&str]| Interpreter::Inline {
+        program: program.to_string(),
+        args: args.iter().map(|a| a.to_string()).collect(),
+    };
+    match executor {
+        "js" | "javascript" => inline("node", &["-e"]),
+        "py" | "python" | "python3" => {
+            #[cfg(not(windows))]
+            let the_executor = "python3";
+
             #[cfg(windows)]
-            "cmd" | "batch" => {
-                let mut child = process::Command::new("cmd.exe");
-                child.arg("/c").arg(source);
-                (child, "cmd.exe".to_string())
+            let the_executor = "python";
+
+            inline(the_executor, &["-c"])
+        }
+        "rb" | "ruby" => inline("ruby", &["-e"]),
+        "php" => inline("php", &["-r"]),
+        "ts" | "typescript" => inline("deno", &["eval", "--ext=ts"]),
+        "go" => inline("yaegi", &["-e"]),
+        // If no language is specified, we use the default shell
+        "" | "sh" | "bash" | "zsh" | "dash" => Interpreter::Shell {
+            program: if executor.is_empty() { "sh" } else { executor }.to_string(),
+        },
+        #[cfg(windows)]
+        "cmd" | "batch" => inline("cmd.exe", &["/c"]),
+        #[cfg(windows)]
+        "powershell" => inline("powershell.exe", &["-c"]),
+        // Any other executor that supports -c (fish, etc...)
+        _ => inline(executor, &["-c"]), // cov:ignore
+    }
+}
+
+impl Executor for Interpreter {
+    fn prepare(&self, script: &Script, ctx: &ExecContext) -> io::Result<PreparedCommand> {
+        let source = script.source.trim();
+        match self {
+            Interpreter::Shebang { .. } => {
+                let hash = hash_source(source);
+                let mut tempfile = ctx.dir.join(format!(".inkjet-order.{hash}"));
+                if std::fs::write(&tempfile, source).is_err() {
+                    // The directory may be read-only, i.e. for a bundled CLI installed system-wide
+                    tempfile = env::temp_dir().join(format!(".inkjet-order.{hash}"));
+                    std::fs::write(&tempfile, source)?;
+                }
+
+                #[cfg(not(windows))]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let mut perms = std::fs::metadata(&tempfile)?.permissions();
+                    perms.set_mode(0o775);
+                    std::fs::set_permissions(&tempfile, perms)?;
+                }
+
+                let mut prepared =
+                    PreparedCommand::new(process::Command::new(&tempfile), self.program());
+                prepared.tempfile = Some(tempfile);
+                Ok(prepared)
             }
-            #[cfg(windows)]
-            "powershell" => {
-                let mut child = process::Command::new("powershell.exe");
-                child.arg("-c").arg(source);
-                (child, "powershell.exe".to_string())
+            Interpreter::Shell { program } => {
+                let mut child = process::Command::new(program);
+                let top = "set -e"; // a sane default for scripts
+                let src = format!("{top}\n{source}");
+                child.arg("-c").arg(src);
+                let mut prepared = PreparedCommand::new(child, program);
+                prepared.injected_lines = 1;
+                Ok(prepared)
             }
-            // Any other executor that supports -c (fish, etc...)
-            _ => {
-                let mut child = process::Command::new(&executor); // cov:ignore
-                child.arg("-c").arg(source); // cov:ignore
-                (child, executor) // cov:ignore
+            Interpreter::Inline { program, args } => {
+                let mut child = process::Command::new(program);
+                child.args(args).arg(source);
+                Ok(PreparedCommand::new(child, program))
             }
         }
     }
 }
 
+/// ExecContext describes where a script is run. It is passed to `Executor::prepare`.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecContext<'a> {
+    /// The language code of the code block, i.e. `sh` or `python`. A code block without one is `sh`.
+    pub language: &'a str,
+    /// The directory of the inkfile the command was defined in. Files the script needs can be written here.
+    pub dir: &'a Path,
+    /// The name of the command
+    pub command: &'a str,
+}
+
+/// PreparedCommand is a child process that is ready to run a script.
+/// Inkjet adds its environment variables and working directory before the process is spawned.
+#[derive(Debug)]
+pub struct PreparedCommand {
+    /// The process to spawn
+    pub command: process::Command,
+    /// The name of the program that runs the script. It is used in error messages.
+    pub program: String,
+    /// A file that was written for the script. It is deleted after the script exits.
+    pub tempfile: Option<PathBuf>,
+    /// The number of lines added above the script, i.e. 1 for the `set -e` of shell scripts.
+    /// Error line numbers are shifted by this amount when they are mapped back to the inkfile.
+    pub injected_lines: usize,
+}
+
+impl PreparedCommand {
+    #[must_use]
+    /// Create a PreparedCommand that runs command
+    pub fn new(command: process::Command, program: impl Into<String>) -> Self {
+        PreparedCommand {
+            command,
+            program: program.into(),
+            tempfile: None,
+            injected_lines: 0,
+        }
+    }
+
+    /// Deletes the file that was written for the script, if any
+    fn delete_tempfile(&self) -> Result<(), Error> {
+        match &self.tempfile {
+            Some(file) => std::fs::remove_file(file).map_err(|source| Error::TempFile {
+                path: file.display().to_string(),
+                source,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Executor builds the process that runs a code block.
+/// Implement it to run code blocks with a language code that inkjet does not support, then add it to an ExecutorRegistry.
+pub trait Executor {
+    /// Builds the process that runs script
+    fn prepare(&self, script: &Script, ctx: &ExecContext) -> io::Result<PreparedCommand>;
+}
+
+/// ExecutorRegistry maps the language codes of code blocks to Executors.
+/// Languages without a registered Executor use the built-in Interpreter for that language code.
+#[derive(Default)]
+pub struct ExecutorRegistry {
+    executors: HashMap<String, Box<dyn Executor>>,
+}
+
+impl ExecutorRegistry {
+    #[must_use]
+    /// Create a registry with only the built-in executors
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers executor for a language code. A registered executor replaces the built-in one and also runs
+    /// scripts that start with a shebang.
+    pub fn register(
+        &mut self,
+        language: impl Into<String>,
+        executor: impl Executor + 'static,
+    ) -> &mut Self {
+        self.executors.insert(language.into(), Box::new(executor));
+        self
+    }
+
+    /// Returns true if a custom executor is registered for the language code
+    pub fn contains(&self, language: &str) -> bool {
+        self.executors.contains_key(language)
+    }
+
+    /// Builds the process for a script with the executor registered for its language code
+    pub fn prepare(&self, script: &Script, ctx: &ExecContext) -> io::Result<PreparedCommand> {
+        match self.executors.get(ctx.language) {
+            Some(executor) => executor.prepare(script, ctx),
+            None => resolve_interpreter(ctx.language, &script.source).prepare(script, ctx),
+        }
+    }
+}
+
+impl std::fmt::Debug for ExecutorRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExecutorRegistry")
+            .field("executors", &self.executors.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
 /// Find the absolute path to the inkfile's parent directory
 fn get_parent_dir(inkfile_path: &str) -> String {
     Path::new(&inkfile_path)
@@ -279,10 +857,10 @@ fn get_parent_dir(inkfile_path: &str) -> String {
 
 /// Add some useful environment variables that scripts can use
 fn add_utility_variables(
-    mut child: process::Command,
+    child: &mut process::Command,
     inkfile_path: &str,
     local_inkfile_path: &str,
-) -> process::Command {
+) {
     let exe_path = match env::current_exe() {
         Ok(path) => path.to_string_lossy().into_owned(),
         _ => "inkjet".to_owned(),
@@ -291,24 +869,34 @@ fn add_utility_variables(
     // inside scripts so that they can be location-agnostic (not care where they are
     // called from). This is useful for global inkfiles especially.
     // $INKJET always refers to the root inkjet script
-    child.env("INKJET", format!("{exe_path} --inkfile {inkfile_path}"));
-    // $INK is shorthand for "$INKJET command". The difference here is that it resolves to the local inkjet.md which
-    // could differ from $INKJET if the file was imported.
-    child.env("INK", format!("{exe_path} --inkfile {local_inkfile_path}"));
+    // A bundled executable is its own inkfile, so it is called without --inkfile.
+    if exe_path == inkfile_path {
+        child.env("INKJET", &exe_path);
+        child.env("INK", &exe_path);
+    } else {
+        child.env("INKJET", format!("{exe_path} --inkfile {inkfile_path}"));
+        // $INK is shorthand for "$INKJET command". The difference here is that it resolves to the local inkjet.md which
+        // could differ from $INKJET if the file was imported.
+        child.env("INK", format!("{exe_path} --inkfile {local_inkfile_path}"));
+    }
     // This allows us to refer to the directory the inkfile lives in which can be handy
     // for loading relative files to it.
     child.env("INKJET_DIR", get_parent_dir(inkfile_path));
     // This is the same as INKJET_DIR, but could differ for imported inkjet.md files.
-    child.env("INK_DIR", get_parent_dir(local_inkfile_path));
+    // The imports of a bundle are not on disk, so they share the directory of the executable.
+    if exe_path == inkfile_path {
+        child.env("INK_DIR", get_parent_dir(inkfile_path));
+    } else {
+        child.env("INK_DIR", get_parent_dir(local_inkfile_path));
+    }
     // Environment variable is set if this file was imported from another.
     if local_inkfile_path != inkfile_path {
         child.env("INKJET_IMPORTED", "true");
     }
-
-    child
 }
 
-fn add_flag_variables(mut child: process::Command, cmd: &CommandBlock) -> process::Command {
+#[allow(clippy::unnecessary_unwrap)]
+fn add_flag_variables(child: &mut process::Command, cmd: &CommandBlock) {
     // Add all required args as environment variables
     for arg in &cmd.args {
         let val = if arg.val.is_empty() && arg.default.is_some() {
@@ -327,6 +915,4 @@ fn add_flag_variables(mut child: process::Command, cmd: &CommandBlock) -> proces
             child.env(flag.name.replace("-", "_"), flag.val.clone());
         }
     }
-
-    child
 }
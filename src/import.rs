@@ -0,0 +1,359 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::command::CommandBlock;
+use crate::parser::{self, InkjetConfig, ParseError};
+use crate::utils;
+
+/// ImportSpec is a single entry of the `import` directive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportSpec {
+    /// `all` merges every `inkjet.md` and `*.inkjet.md` file found below the importing inkfile.
+    All,
+    /// A single inkfile relative to the importing inkfile, i.e. `./services/api/inkjet.md as api`.
+    /// If a namespace is given, the imported commands are mounted under a command of that name.
+    File {
+        /// The path of the imported inkfile as written in the directive
+        path: String,
+        /// The name of the command the imported commands are mounted under
+        namespace: Option<String>,
+    },
+}
+
+impl ImportSpec {
+    /// Parse a single import directive entry
+    pub fn parse(spec: &str) -> Result<ImportSpec, String> {
+        let spec = spec.trim();
+        if spec == "all" {
+            return Ok(ImportSpec::All);
+        }
+        let split = spec
+            .rsplit_once(" as ")
+            .or_else(|| spec.strip_prefix("as ").map(|ns| ("", ns)));
+        let (path, namespace) = match split {
+            Some((path, namespace)) => (path.trim(), Some(namespace.trim().to_lowercase())),
+            None => (spec, None),
+        };
+        if path.is_empty() {
+            return Err(format!(
+                "Invalid import \"{spec}\". Expected a path to an inkfile."
+            ));
+        }
+        if let Some(ns) = &namespace
+            && (ns.is_empty() || ns.contains(char::is_whitespace))
+        {
+            return Err(format!(
+                "Invalid import \"{spec}\". Namespaces must be a single word."
+            ));
+        }
+        Ok(ImportSpec::File {
+            path: path.to_string(),
+            namespace,
+        })
+    }
+}
+
+/// Source is the path and contents of an inkfile that contributed to a resolved command tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    /// The path of the inkfile
+    pub path: String,
+    /// The markdown contents of the inkfile
+    pub contents: String,
+}
+
+/// ResolvedInkfile is the command tree of an inkfile with all of its imports mounted. See `Inkfile` for the public API.
+#[derive(Debug, Clone)]
+pub struct ResolvedInkfile {
+    /// The root of the merged CommandBlock tree
+    pub root: CommandBlock,
+    /// Every inkfile that contributed to the tree in the order they were imported. The first entry is the root inkfile.
+    pub sources: Vec<Source>,
+}
+
+/// Parses an inkfile and recursively mounts every inkfile listed in its `import` directive.
+/// Each file is parsed on its own so imported commands keep their own working directory and heading levels.
+/// Importing a file that is currently being imported is reported as a cycle.
+pub fn resolve(
+    inkfile_contents: &str,
+    inkfile_path: &str,
+    config: &InkjetConfig,
+    log_warnings: bool,
+) -> Result<ResolvedInkfile, ParseError> {
+    let mut resolver = Resolver {
+        stack: vec![],
+        visited: HashSet::new(),
+        sources: vec![Source {
+            path: inkfile_path.to_string(),
+            contents: inkfile_contents.to_string(),
+        }],
+        log_warnings,
+    };
+    let root = resolver.resolve_file(Path::new(inkfile_path), inkfile_contents, config)?;
+    let root = parser::finalize_tree(root, inkfile_path, log_warnings)?;
+    Ok(ResolvedInkfile {
+        root,
+        sources: resolver.sources,
+    })
+}
+
+struct Resolver {
+    /// The files currently being imported. Used for cycle detection.
+    stack: Vec<PathBuf>,
+    /// Every file that has been imported. `all` imports skip these.
+    visited: HashSet<PathBuf>,
+    sources: Vec<Source>,
+    log_warnings: bool,
+}
+
+impl Resolver {
+    fn resolve_file(
+        &mut self,
+        path: &Path,
+        contents: &str,
+        config: &InkjetConfig,
+    ) -> Result<CommandBlock, ParseError> {
+        let file_str = path.to_string_lossy();
+        let (mut root, _) = parser::parse_document(contents, &file_str, self.log_warnings)
+            .map_err(|e| e.with_file(&file_str))?;
+        let specs = config
+            .import
+            .iter()
+            .map(|s| ImportSpec::parse(s))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ParseError::Import)?;
+        let key = canonical(path);
+        self.stack.push(key.clone());
+        self.visited.insert(key);
+
+        // Files that are imported explicitly are skipped by `all` so that they are only mounted once
+        let mut explicit = vec![];
+        for spec in &specs {
+            if let ImportSpec::File { path: rel, .. } = spec {
+                explicit.push(canonical(&relative_to(path, rel)));
+            }
+        }
+        for spec in specs {
+            match spec {
+                ImportSpec::All => {
+                    for file in discover_inkfiles(path, config).map_err(ParseError::Import)? {
+                        if self.visited.contains(&file) || explicit.contains(&file) {
+                            continue;
+                        }
+                        let child = self.import_file(&file)?;
+                        mount(&mut root, child, None);
+                    }
+                }
+                ImportSpec::File {
+                    path: rel,
+                    namespace,
+                } => {
+                    let file = relative_to(path, &rel);
+                    if !file.is_file() {
+                        return Err(ParseError::Import(format!(
+                            "Unable to import \"{rel}\" from {}: file not found",
+                            path.display()
+                        )));
+                    }
+                    let file = canonical(&file);
+                    if self.stack.contains(&file) {
+                        let mut cycle: Vec<String> =
+                            self.stack.iter().map(|p| p.display().to_string()).collect();
+                        cycle.push(file.display().to_string());
+                        return Err(ParseError::Import(format!(
+                            "Import cycle detected: {}",
+                            cycle.join(" -> ")
+                        )));
+                    }
+                    // A file that `all` or another import already mounted is not mounted twice
+                    if self.visited.contains(&file) {
+                        if self.log_warnings {
+                            eprintln!(
+                                "{} {} is already imported. The import from {} was skipped.",
+                                utils::info_msg(),
+                                file.display(),
+                                path.display()
+                            );
+                        }
+                        continue;
+                    }
+                    let child = self.import_file(&file)?;
+                    mount(&mut root, child, namespace);
+                }
+            }
+        }
+        self.stack.pop();
+        Ok(root)
+    }
+
+    fn import_file(&mut self, file: &Path) -> Result<CommandBlock, ParseError> {
+        let file_str = file.to_string_lossy().to_string();
+        let contents = fs::read_to_string(file)
+            .map_err(|e| ParseError::Import(format!("Error reading file {file_str}: {e}")))?;
+        let config = parser::parse_config(&contents, self.log_warnings)
+            .map_err(|e| e.with_file(&file_str))?;
+        self.sources.push(Source {
+            path: file_str.clone(),
+            contents: contents.clone(),
+        });
+        let mut tree = self.resolve_file(file, &contents, &config)?;
+        set_inkjet_file(&mut tree, &file_str);
+        Ok(tree)
+    }
+}
+
+/// Finds all `inkjet.md` and `*.inkjet.md` files below an inkfile's directory.
+/// Paths matched by `.gitignore` or `.ignore` files, the `import_exclude` globs, and `.git` directories are not searched.
+/// They are sorted by directory depth and then alphabetically so deeper definitions can override shallower ones.
+pub fn discover_inkfiles(
+    inkfile_path: &Path,
+    config: &InkjetConfig,
+) -> Result<Vec<PathBuf>, String> {
+    let mut inkjet_files: Vec<PathBuf> = vec![];
+    for entry in discovery_walk(inkfile_path, config)?.flatten() {
+        let filename = entry.file_name().to_string_lossy();
+        if filename == "inkjet.md" || filename.ends_with(".inkjet.md") {
+            inkjet_files.push(canonical(entry.path()));
+        }
+    }
+    inkjet_files.sort_by(|a, b| {
+        a.components()
+            .count()
+            .cmp(&b.components().count())
+            .then_with(|| a.cmp(b))
+    });
+    Ok(inkjet_files)
+}
+
+/// Returns every directory that `discover_inkfiles` searches and the ignore files found in them.
+/// A change to any of these paths can change which inkfiles are discovered.
+pub fn discovery_paths(inkfile_path: &Path, config: &InkjetConfig) -> Result<Vec<PathBuf>, String> {
+    let mut paths = vec![];
+    for entry in discovery_walk(inkfile_path, config)?.flatten() {
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            for ignore_file in [".gitignore", ".ignore"] {
+                let ignore_path = entry.path().join(ignore_file);
+                if ignore_path.is_file() {
+                    paths.push(ignore_path);
+                }
+            }
+            paths.push(entry.into_path());
+        }
+    }
+    Ok(paths)
+}
+
+/// Walks the directory of an inkfile the way `import = ["all"]` searches it
+fn discovery_walk(inkfile_path: &Path, config: &InkjetConfig) -> Result<ignore::Walk, String> {
+    let parent_dir = inkfile_path.parent().unwrap_or(Path::new("."));
+    let parent_dir = if parent_dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent_dir
+    };
+    let mut overrides = OverrideBuilder::new(parent_dir);
+    for pattern in &config.import_exclude {
+        // Override globs whitelist by default. A leading `!` turns them into ignore globs.
+        overrides
+            .add(&format!("!{pattern}"))
+            .map_err(|e| format!("Invalid import_exclude pattern \"{pattern}\": {e}"))?;
+    }
+    let overrides = overrides
+        .build()
+        .map_err(|e| format!("Invalid import_exclude patterns: {e}"))?;
+    Ok(WalkBuilder::new(parent_dir)
+        .hidden(false)
+        .require_git(false)
+        .max_depth(config.import_max_depth)
+        .overrides(overrides)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build())
+}
+
+/// Mounts an imported tree into its parent. With a namespace (explicit or the imported H1 title), the imported root
+/// becomes a command of that name, keeping its script, args, flags and aliases.
+/// Without one, the imported commands become siblings of the parent's commands.
+fn mount(parent: &mut CommandBlock, child: CommandBlock, namespace: Option<String>) {
+    let namespace = namespace.or_else(|| Some(child.name.clone()).filter(|n| !n.is_empty()));
+    match namespace {
+        Some(name) => {
+            let mut ns_cmd = child;
+            ns_cmd.name = name;
+            set_level(&mut ns_cmd, parent.cmd_level + 1);
+            parent.subcommands.push(ns_cmd);
+        }
+        None => {
+            for mut c in child.subcommands {
+                set_level(&mut c, parent.cmd_level + 1);
+                parent.subcommands.push(c);
+            }
+        }
+    }
+}
+
+fn set_level(cmd: &mut CommandBlock, level: u8) {
+    cmd.cmd_level = level;
+    for c in &mut cmd.subcommands {
+        set_level(c, level + 1);
+    }
+}
+
+/// Commands that were not marked by an `<!-- inkfile: -->` comment belong to the file they were parsed from
+fn set_inkjet_file(cmd: &mut CommandBlock, file: &str) {
+    if cmd.inkjet_file.is_empty() {
+        cmd.inkjet_file = file.to_string();
+    }
+    for c in &mut cmd.subcommands {
+        set_inkjet_file(c, file);
+    }
+}
+
+fn relative_to(inkfile_path: &Path, rel: &str) -> PathBuf {
+    inkfile_path.parent().unwrap_or(Path::new(".")).join(rel)
+}
+
+/// Returns the canonical path if the file exists. Inkfiles read from stdin do not.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod import_spec {
+    use super::*;
+
+    #[test]
+    fn parses_all() {
+        assert_eq!(ImportSpec::parse("all"), Ok(ImportSpec::All));
+    }
+
+    #[test]
+    fn parses_namespace() {
+        assert_eq!(
+            ImportSpec::parse("./services/api/inkjet.md as API"),
+            Ok(ImportSpec::File {
+                path: "./services/api/inkjet.md".to_string(),
+                namespace: Some("api".to_string())
+            })
+        );
+        assert_eq!(
+            ImportSpec::parse("shared.inkjet.md"),
+            Ok(ImportSpec::File {
+                path: "shared.inkjet.md".to_string(),
+                namespace: None
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_namespace() {
+        ImportSpec::parse("./api.md as my api").expect_err("namespace has a space");
+        ImportSpec::parse(" as api").expect_err("path is missing");
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::command::CommandBlock;
+use crate::error::Error;
+use crate::import::{self, Source};
+use crate::parser::{self, InkjetConfig, ParseError};
+
+/// The version of the `--inkjet-dump` schema. It is incremented whenever a field is renamed, removed or changes meaning.
+pub const DUMP_SCHEMA_VERSION: u32 = 1;
+
+/// Inkfile is a parsed inkfile with all of its imports mounted into a single CommandBlock tree.
+///
+/// ```no_run
+/// let inkfile = inkjet::Inkfile::load("inkjet.md")?;
+/// let result = inkjet::ExecutionRequest::new(&inkfile, "services start")?
+///     .arg("api")
+///     .flag("verbose", "true")
+///     .run()?;
+/// println!("{}", result.stdout);
+/// # Ok::<(), inkjet::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Inkfile {
+    /// The path of the inkfile. Scripts run in its directory unless `fixed_dir` is disabled.
+    pub path: String,
+    /// The directives from the front matter
+    pub config: InkjetConfig,
+    /// The root of the CommandBlock tree. Its subcommands are the top level commands.
+    pub root: CommandBlock,
+    /// Every inkfile that contributed to the tree in the order they were imported. The first entry is the root inkfile.
+    pub sources: Vec<Source>,
+}
+
+/// InkfileDump is the document printed by `--inkjet-dump`
+#[derive(Debug, Serialize)]
+pub struct InkfileDump<'a> {
+    /// Always DUMP_SCHEMA_VERSION
+    pub schema_version: u32,
+    /// The path of the root inkfile
+    pub inkfile: &'a str,
+    /// The paths of every inkfile that contributed to the tree. The first entry is the root inkfile.
+    pub sources: Vec<&'a str>,
+    /// The resolved command tree
+    pub root: &'a CommandBlock,
+}
+
+impl Inkfile {
+    /// Reads and parses the inkfile at path. An executable written by `--inkjet-bundle` yields the inkfile bundled into it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Inkfile, Error> {
+        let path = path.as_ref();
+        let io_err = |source| Error::Io {
+            path: path.display().to_string(),
+            source,
+        };
+        let canonical = fs::canonicalize(path).map_err(io_err)?;
+        // An executable written by `--inkjet-bundle` carries its parsed inkfile
+        if let Some(inkfile) = crate::bundle::read(&canonical) {
+            return Ok(inkfile);
+        }
+        let contents = fs::read_to_string(path).map_err(io_err)?;
+        let path = canonical;
+        Inkfile::parse_at(&contents, &path.to_string_lossy())
+    }
+
+    /// Parses inkfile contents as if they were read from `stdin` in the current directory.
+    /// Imports are resolved relative to the current directory.
+    pub fn parse(contents: &str) -> Result<Inkfile, Error> {
+        Inkfile::parse_at(contents, &crate::loader::stdin_name())
+    }
+
+    /// Parses inkfile contents as if they were read from path
+    pub fn parse_at(contents: &str, path: &str) -> Result<Inkfile, Error> {
+        Ok(Inkfile::parse_with(contents, path, false)?)
+    }
+
+    /// Parses inkfile contents, optionally printing warnings for deprecated syntax and merged commands
+    pub(crate) fn parse_with(
+        contents: &str,
+        path: &str,
+        log_warnings: bool,
+    ) -> Result<Inkfile, ParseError> {
+        let config = parser::parse_config(contents, log_warnings)?;
+        let resolved = import::resolve(contents, path, &config, log_warnings)?;
+        Ok(Inkfile {
+            path: path.to_string(),
+            config,
+            root: resolved.root,
+            sources: resolved.sources,
+        })
+    }
+
+    /// Finds a command by its space separated path, i.e. `services start`. Aliases are accepted.
+    pub fn find(&self, path: &str) -> Option<&CommandBlock> {
+        self.root.find(path)
+    }
+
+    /// Returns the markdown for the inkfile a CommandBlock was defined in.
+    /// Commands of the root inkfile have an empty `inkjet_file`.
+    pub fn source_of(&self, cmd: &CommandBlock) -> &str {
+        let root = self.sources.first().map_or("", |s| s.contents.as_str());
+        if cmd.inkjet_file.is_empty() {
+            return root;
+        }
+        self.sources
+            .iter()
+            .find(|s| s.path == cmd.inkjet_file)
+            .map_or(root, |s| s.contents.as_str())
+    }
+
+    /// Returns the contents of every source, prefixed with an `<!-- inkfile: -->` marker when imports were merged.
+    pub fn merged_text(&self) -> String {
+        if self.sources.len() == 1 {
+            return self
+                .sources
+                .first()
+                .map(|s| s.contents.clone())
+                .unwrap_or_default();
+        }
+        let mut combined_text = String::new();
+        for source in &self.sources {
+            combined_text.push_str(&format!("<!-- inkfile: {} -->\n", source.path));
+            combined_text.push_str(&source.contents);
+        }
+        combined_text
+    }
+
+    /// Replaces the script of cmd with source in the inkfile that defined it and writes that file.
+    /// The file must not have changed since it was parsed. The inkfile is parsed again afterwards,
+    /// so the commands that follow the edit point at their new positions. Returns the path of the file that was written.
+    pub fn save_script(&mut self, cmd: &CommandBlock, source: &str) -> Result<String, Error> {
+        let path = if cmd.inkjet_file.is_empty() {
+            &self.path
+        } else {
+            &cmd.inkjet_file
+        };
+        let file = self
+            .sources
+            .iter()
+            .find(|s| &s.path == path)
+            .ok_or_else(|| Error::SourceNotFound {
+                command: cmd.name.clone(),
+                path: path.clone(),
+            })?;
+        let io_err = |source| Error::Io {
+            path: file.path.clone(),
+            source,
+        };
+        let script = &cmd.script.source;
+        let start = cmd.script.offset;
+        let found = !script.is_empty()
+            && file
+                .contents
+                .get(start..)
+                .is_some_and(|body| body.starts_with(script.as_str()));
+        if !found {
+            return Err(io_err(io::Error::other(format!(
+                "the code block of {} was not found",
+                cmd.name
+            ))));
+        }
+        let on_disk = fs::read_to_string(&file.path).map_err(io_err)?;
+        if on_disk != file.contents {
+            return Err(io_err(io::Error::other(
+                "the inkfile changed since it was read",
+            )));
+        }
+        let mut contents = file.contents.clone();
+        let mut source = source.to_string();
+        if !source.ends_with('\n') {
+            source.push('\n');
+        }
+        contents.replace_range(start..start + script.len(), &source);
+        fs::write(&file.path, &contents).map_err(io_err)?;
+        let written = file.path.clone();
+        // Imports are read again from disk, so only the root inkfile needs its new contents
+        let root = if written == self.path {
+            contents
+        } else {
+            self.sources
+                .first()
+                .map(|s| s.contents.clone())
+                .unwrap_or_default()
+        };
+        *self = Inkfile::parse_with(&root, &self.path, false)?;
+        Ok(written)
+    }
+
+    /// Returns the versioned document that `--inkjet-dump` serializes
+    pub fn dump(&self) -> InkfileDump<'_> {
+        InkfileDump {
+            schema_version: DUMP_SCHEMA_VERSION,
+            inkfile: &self.path,
+            sources: self.sources.iter().map(|s| s.path.as_str()).collect(),
+            root: &self.root,
+        }
+    }
+}
@@ -4,16 +4,44 @@
 //! Make your markdown executable with inkjet, the interactive CLI task runner
 #![warn(clippy::indexing_slicing)]
 #![warn(missing_docs)]
+pub use error::Error;
+pub use executor::{ExecutionRequest, ExecutionResult};
+pub use inkfile::Inkfile;
+
+/// The `inkjet::answers` module remembers the values given to interactive prompts so they become the next defaults.
+pub mod answers;
+/// The `inkjet::bundle` module writes and reads inkfiles bundled into a copy of the inkjet executable.
+pub mod bundle;
+/// The `inkjet::cache` module stores parsed inkfiles so that completions and help skip the parse.
+pub mod cache;
 /// The `inkjet::command` module holds CommandBlock and its types
 pub mod command;
+/// The `inkjet::completions` module installs the shell wrappers that request completions from inkjet.
+pub mod completions;
+/// The `inkjet::docs` module renders a markdown or HTML reference of the commands in an inkfile.
+pub mod docs;
+/// The `inkjet::error` module holds the Error type returned by the library API
+pub mod error;
 /// The `inkjet::executor` module contains the implementations to prepare and execute a CommandBlock
 pub mod executor;
+/// The `inkjet::import` module resolves the `import` directive and mounts imported inkfiles into a CommandBlock tree.
+pub mod import;
+/// The `inkjet::inkfile` module holds Inkfile, the entry point for parsing inkfiles as a library.
+pub mod inkfile;
+/// The `inkjet::lint` module runs the semantic checks of `inkjet --inkjet-check` across a CommandBlock tree.
+pub mod lint;
 /// The `inkjet::loader` module contains the implementations to read and inkfile from disk or stdin prior to parsing.
 pub mod loader;
 /// The `inkjet::parser` module is responsible for parsing a markdown string and returning a CommandBlock tree.
 pub mod parser;
+/// The `inkjet::picker` module lets the user search for a command when none is given.
+pub mod picker;
+/// The `inkjet::runbook` module steps through every command of an inkfile in document order.
+pub mod runbook;
 /// The `inkjet::runner` module contains the main inkjet CLI logic. Call `inkjet::runner::run` with args and color setting.
 pub mod runner;
+/// The `inkjet::session` module runs the shell blocks of successive steps in one long-lived shell.
+pub mod session;
 /// utils used internally (mainly color)
 pub mod utils;
 /// The `inkjet::view` module contains the implementation for printing markdown to the terminal. It is used for interactive mode.
@@ -0,0 +1,577 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::{env, process};
+
+use crate::command::CommandBlock;
+use crate::executor::{self, Interpreter};
+use crate::inkfile::Inkfile;
+use crate::parser::{self, Location};
+use crate::utils;
+
+/// Severity describes whether a Diagnostic breaks the inkfile or is only suspicious.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The inkfile will not work as written
+    Error,
+    /// The inkfile works but likely not as intended
+    Warning,
+}
+
+/// Diagnostic is a single problem found by `inkjet --inkjet-check`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    /// Whether this problem is an error or a warning
+    pub severity: Severity,
+    /// A description of the problem
+    pub message: String,
+    /// The path of the command the problem belongs to, i.e. `deploy prod`. It is empty for document level problems.
+    pub command: String,
+    /// The inkfile that contains the problem
+    pub file: String,
+    /// The line number, starting at 1. It is 0 when the line is not known.
+    pub line: usize,
+    /// The column in characters, starting at 1. It is 0 when the column is not known.
+    pub column: usize,
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic as a single line in the form of `SEVERITY file:line:column message`
+    pub fn render(&self, color: bool) -> String {
+        let prefix = match (self.severity, color) {
+            (Severity::Error, true) => utils::error_msg(),
+            (Severity::Error, false) => utils::plain_msg("ERROR"),
+            (Severity::Warning, true) => utils::warning_msg(),
+            (Severity::Warning, false) => utils::plain_msg("WARNING"),
+        };
+        if self.line == 0 {
+            format!("{prefix} {}: {}", self.file, self.message)
+        } else {
+            format!(
+                "{prefix} {}:{}:{}: {}",
+                self.file, self.line, self.column, self.message
+            )
+        }
+    }
+}
+
+/// Returns true if any of the diagnostics is an error
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
+
+/// Parses an inkfile and its imports and runs semantic checks across the whole CommandBlock tree.
+/// If `scripts` is set, each script is also checked for syntax errors by its interpreter.
+/// Every problem that is found is returned. A parse error stops the checks and is returned on its own.
+pub fn check(inkfile_contents: &str, inkfile_path: &str, scripts: bool) -> Vec<Diagnostic> {
+    let inkfile = match Inkfile::parse_with(inkfile_contents, inkfile_path, false) {
+        Ok(inkfile) => inkfile,
+        Err(err) => {
+            let err = err.with_file(inkfile_path);
+            let location = err.location().cloned().unwrap_or_default();
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                message: err.to_string(),
+                command: "".to_string(),
+                file: if location.file.is_empty() {
+                    inkfile_path.to_string()
+                } else {
+                    location.file
+                },
+                line: location.line,
+                column: location.column,
+            }];
+        }
+    };
+    check_inkfile(&inkfile, scripts)
+}
+
+/// Runs the checks of `check` on an inkfile that is already parsed, such as the one bundled into an executable.
+pub fn check_inkfile(inkfile: &Inkfile, scripts: bool) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for source in &inkfile.sources {
+        // Each source parsed before, so errors cannot happen here
+        if let Ok((_, warnings)) = parser::read_config(&source.contents) {
+            for message in warnings {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    message,
+                    command: "".to_string(),
+                    file: source.path.clone(),
+                    line: 0,
+                    column: 0,
+                });
+            }
+        }
+        if let Ok((_, removed)) = parser::parse_document(&source.contents, &source.path, false) {
+            for cmd in removed {
+                let location = Location::new(&source.contents, 0, &source.path, cmd.start, 0);
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    message: format!(
+                        "command \"{}\" has no code block or subcommands and was removed",
+                        cmd.name
+                    ),
+                    command: cmd.name.clone(),
+                    file: location.file,
+                    line: location.line,
+                    column: location.column,
+                });
+            }
+        }
+    }
+    let checker = Checker { inkfile, scripts };
+    for cmd in &inkfile.root.subcommands {
+        checker.check_command(cmd, "", &mut diagnostics);
+    }
+    diagnostics
+}
+
+struct Checker<'a> {
+    inkfile: &'a Inkfile,
+    scripts: bool,
+}
+
+impl Checker<'_> {
+    fn check_command(&self, cmd: &CommandBlock, parent: &str, diagnostics: &mut Vec<Diagnostic>) {
+        let path = if parent.is_empty() {
+            cmd.name.clone()
+        } else {
+            format!("{parent} {}", cmd.name)
+        };
+        let mut report = |severity: Severity, message: String| {
+            diagnostics.push(self.diagnostic(cmd, &path, severity, message));
+        };
+
+        let mut shorts: HashMap<&str, &str> = HashMap::new();
+        let mut longs: HashMap<&str, &str> = HashMap::new();
+        for flag in &cmd.named_flags {
+            if flag.short.is_empty() && flag.long.is_empty() {
+                report(
+                    Severity::Error,
+                    format!(
+                        "flag \"{}\" has no `flag:` definition so it cannot be passed",
+                        flag.name
+                    ),
+                );
+            }
+            if !flag.choices.is_empty() && !flag.takes_value {
+                report(
+                    Severity::Error,
+                    format!(
+                        "flag \"{}\" has choices but is a boolean. Set `type: string` to use choices",
+                        flag.name
+                    ),
+                );
+            }
+            if !flag.short.is_empty()
+                && let Some(other) = shorts.insert(&flag.short, &flag.name)
+            {
+                report(
+                    Severity::Error,
+                    format!(
+                        "flags \"{other}\" and \"{}\" both use the short flag -{}",
+                        flag.name, flag.short
+                    ),
+                );
+            }
+            if !flag.long.is_empty()
+                && let Some(other) = longs.insert(&flag.long, &flag.name)
+            {
+                report(
+                    Severity::Error,
+                    format!(
+                        "flags \"{other}\" and \"{}\" both use the long flag --{}",
+                        flag.name, flag.long
+                    ),
+                );
+            }
+        }
+
+        let mut multiple_arg: Option<&str> = None;
+        for arg in &cmd.args {
+            if arg.last {
+                continue;
+            }
+            if let Some(multiple) = multiple_arg {
+                report(
+                    Severity::Error,
+                    format!(
+                        "arg \"{}\" comes after \"{multiple}\" which accepts multiple values, so it never receives a value",
+                        arg.name
+                    ),
+                );
+            } else if arg.multiple {
+                multiple_arg = Some(&arg.name);
+            }
+        }
+
+        if self.scripts && cmd.script.has_script() {
+            self.check_script(cmd, &path, diagnostics);
+        }
+        for sub in &cmd.subcommands {
+            self.check_command(sub, &path, diagnostics);
+        }
+    }
+
+    /// Runs the interpreter of a script in check-only mode. The interpreter is resolved just like when the command runs.
+    /// The reported lines are mapped back to the markdown by counting from the first line of the code block.
+    fn check_script(&self, cmd: &CommandBlock, path: &str, diagnostics: &mut Vec<Diagnostic>) {
+        let interpreter = executor::resolve_interpreter(&cmd.script.executor, &cmd.script.source);
+        let program = interpreter.program();
+        let (args, extension): (&[&str], &str) = match program.as_str() {
+            "sh" | "bash" | "dash" | "zsh" | "ksh" => (&["-n"], "sh"),
+            "python" | "python3" => (&["-m", "py_compile"], "py"),
+            "node" => (&["--check"], "js"),
+            _ => return,
+        };
+        // A shebang script is executed as a file, so its first line is the shebang itself.
+        // Other scripts are checked as written in the code block.
+        let source = match interpreter {
+            Interpreter::Shebang { .. } => cmd.script.source.trim_start(),
+            _ => cmd.script.source.as_str(),
+        };
+        // A named temporary file cannot be predicted or replaced by another user, and it is removed when dropped
+        let script_file = tempfile::Builder::new()
+            .prefix("inkjet-check.")
+            .suffix(&format!(".{extension}"))
+            .tempfile()
+            .and_then(|mut file| file.write_all(source.as_bytes()).map(|_| file));
+        let script_file = match script_file {
+            Ok(file) => file,
+            Err(err) => {
+                diagnostics.push(self.diagnostic(
+                    cmd,
+                    path,
+                    Severity::Warning,
+                    format!("could not check script: {err}"),
+                ));
+                return;
+            }
+        };
+        let script_path = script_file.path().to_string_lossy().to_string();
+
+        let mut child = process::Command::new(&program);
+        child
+            .args(args)
+            .arg(&script_path)
+            // py_compile writes bytecode next to the file unless a cache prefix is set
+            .env(
+                "PYTHONPYCACHEPREFIX",
+                env::temp_dir().join("inkjet-pycache"),
+            );
+        match child.output() {
+            Ok(output) if !output.status.success() => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let report = if stderr.trim().is_empty() {
+                    stdout
+                } else {
+                    stderr
+                };
+                let (line, message) = parse_checker_output(&report, &script_path);
+                diagnostics.push(self.script_diagnostic(
+                    cmd,
+                    path,
+                    Severity::Error,
+                    format!("{program}: {message}"),
+                    line,
+                    1,
+                ));
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                diagnostics.push(self.diagnostic(
+                    cmd,
+                    path,
+                    Severity::Warning,
+                    format!("could not check script: {program} is not installed"),
+                ));
+            }
+            Err(err) => {
+                diagnostics.push(self.diagnostic(
+                    cmd,
+                    path,
+                    Severity::Warning,
+                    format!("could not check script: {err}"),
+                ));
+            }
+        }
+
+        // shellcheck is optional. It is skipped when it is not installed.
+        if matches!(program.as_str(), "sh" | "bash" | "dash" | "ksh") {
+            let shellcheck = process::Command::new("shellcheck")
+                .arg(format!("--shell={program}"))
+                .arg("--format=gcc")
+                .arg("--severity=warning")
+                .arg(&script_path)
+                .output();
+            if let Ok(output) = shellcheck {
+                let gcc_line = Regex::new(r"^.*?:(\d+):(\d+): (\w+): (.*)$").unwrap();
+                for report in String::from_utf8_lossy(&output.stdout).lines() {
+                    if let Some(caps) = gcc_line.captures(report) {
+                        let number = |i: usize| {
+                            caps.get(i)
+                                .and_then(|m| m.as_str().parse().ok())
+                                .unwrap_or(0)
+                        };
+                        let severity = match caps.get(3).map(|m| m.as_str()) {
+                            Some("error") => Severity::Error,
+                            _ => Severity::Warning,
+                        };
+                        let message = caps.get(4).map_or("", |m| m.as_str());
+                        diagnostics.push(self.script_diagnostic(
+                            cmd,
+                            path,
+                            severity,
+                            format!("shellcheck: {message}"),
+                            number(1),
+                            number(2),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Creates a Diagnostic for a line of a script. `script_line` counts from the first line of the code block.
+    fn script_diagnostic(
+        &self,
+        cmd: &CommandBlock,
+        path: &str,
+        severity: Severity,
+        message: String,
+        script_line: usize,
+        column: usize,
+    ) -> Diagnostic {
+        let mut diagnostic = self.diagnostic(cmd, path, severity, message);
+        // A shebang script is trimmed before it is written, so count from the first line with content
+        let skipped = match executor::resolve_interpreter(&cmd.script.executor, &cmd.script.source)
+        {
+            Interpreter::Shebang { .. } => cmd
+                .script
+                .source
+                .lines()
+                .take_while(|l| l.trim().is_empty())
+                .count(),
+            _ => 0,
+        };
+        if script_line > 0 {
+            diagnostic.line = cmd.script.line + skipped + script_line - 1;
+            diagnostic.column = column.max(1);
+        }
+        diagnostic
+    }
+
+    fn diagnostic(
+        &self,
+        cmd: &CommandBlock,
+        path: &str,
+        severity: Severity,
+        message: String,
+    ) -> Diagnostic {
+        let file = if cmd.inkjet_file.is_empty() {
+            self.inkfile.path.as_str()
+        } else {
+            cmd.inkjet_file.as_str()
+        };
+        let location = Location::new(self.inkfile.source_of(cmd), 0, file, cmd.start, 0);
+        Diagnostic {
+            severity,
+            message: format!("command \"{path}\": {message}"),
+            command: path.to_string(),
+            file: location.file,
+            line: location.line,
+            column: location.column,
+        }
+    }
+}
+
+/// Finds the line number and message in the output of an interpreter's syntax check.
+/// The formats of `sh -n`, `bash -n`, `python3 -m py_compile` and `node --check` are understood.
+fn parse_checker_output(output: &str, script_path: &str) -> (usize, String) {
+    let path = regex::escape(script_path);
+    // bash: "file: line 3: msg", dash: "file: 3: msg", python: "File \"file\", line 3", node: "file:3"
+    let line_re = Regex::new(&format!(
+        r#"{path}(?:"?,? line |: line |: |:)(\d+):? ?(.*)"#
+    ))
+    .unwrap();
+    let error_re = Regex::new(r"^\w*(Error|Exception)\b.*").unwrap();
+    let mut line = 0;
+    let mut message = "".to_string();
+    if let Some(caps) = line_re.captures(output) {
+        line = caps
+            .get(1)
+            .and_then(|m| m.as_str().parse().ok())
+            .unwrap_or(0);
+        message = caps.get(2).map_or("", |m| m.as_str()).trim().to_string();
+    }
+    // python and node print the error name on a line of its own
+    if let Some(error) = output.lines().find(|l| error_re.is_match(l)) {
+        message = error.trim().to_string();
+    }
+    if message.is_empty() {
+        message = output
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or("syntax check failed")
+            .trim()
+            .to_string();
+    }
+    (line, message)
+}
+
+#[cfg(test)]
+mod check {
+    use super::*;
+
+    fn messages(contents: &str) -> Vec<String> {
+        check(contents, "inkjet.md", false)
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn finds_flag_problems() {
+        let found = messages(
+            r#"
+## build
+
+**OPTIONS**
+
+- target
+  - desc: The target to build
+- mode
+  - flag: -m --mode
+  - choices: debug, release
+- flag: -v --version Print the version
+
+```
+echo "build"
+```
+"#,
+        );
+        assert_eq!(found.len(), 3, "{found:?}");
+        assert!(
+            found
+                .iter()
+                .any(|m| m.contains("\"target\" has no `flag:`"))
+        );
+        assert!(found.iter().any(|m| m.contains("\"mode\" has choices")));
+        assert!(found.iter().any(|m| m.contains("short flag -v")));
+    }
+
+    #[test]
+    fn finds_args_after_multiple() {
+        let found = messages("## copy (files...) (dest)\n\n```\necho $files\n```\n");
+        assert_eq!(
+            found,
+            vec![
+                "command \"copy\": arg \"dest\" comes after \"files\" which accepts multiple values, so it never receives a value"
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_removed_commands() {
+        let diagnostics = check(
+            "## docs\n\nJust text\n\n## run\n\n```\necho run\n```\n",
+            "inkjet.md",
+            false,
+        );
+        assert_eq!(diagnostics.len(), 1);
+        let warning = diagnostics.first().expect("warning missing");
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(warning.line, 1);
+        assert!(!has_errors(&diagnostics));
+    }
+
+    #[test]
+    fn warns_about_the_config() {
+        let found = messages("+++\nsorted = true\n+++\n\n## run\n\n```\necho run\n```\n");
+        assert_eq!(
+            found,
+            vec!["Unknown front matter key \"sorted\" was ignored"]
+        );
+
+        let found = messages("inkjet_sort: true\n\n## run\n\n```\necho run\n```\n");
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert!(
+            found
+                .iter()
+                .any(|m| m.contains("\"inkjet_sort: true\" is deprecated"))
+        );
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let diagnostics = check("## a b\n\n```\necho\n```\n", "inkjet.md", false);
+        assert!(has_errors(&diagnostics));
+        assert_eq!(
+            diagnostics.first().map(|d| (d.line, d.column)),
+            Some((1, 4))
+        );
+    }
+
+    #[test]
+    fn parses_checker_output() {
+        let path = "/tmp/inkjet-check.1.sh";
+        assert_eq!(
+            parse_checker_output(
+                "/tmp/inkjet-check.1.sh: line 3: syntax error: unexpected end of file\n",
+                path
+            ),
+            (3, "syntax error: unexpected end of file".to_string())
+        );
+        assert_eq!(
+            parse_checker_output(
+                "/tmp/inkjet-check.1.sh: 3: Syntax error: end of file unexpected (expecting \"fi\")\n",
+                path
+            ),
+            (
+                3,
+                "Syntax error: end of file unexpected (expecting \"fi\")".to_string()
+            )
+        );
+        let path = "/tmp/inkjet-check.1.py";
+        assert_eq!(
+            parse_checker_output(
+                "  File \"/tmp/inkjet-check.1.py\", line 2\n    print(\n         ^\nSyntaxError: '(' was never closed\n",
+                path
+            ),
+            (2, "SyntaxError: '(' was never closed".to_string())
+        );
+        let path = "/tmp/inkjet-check.1.js";
+        assert_eq!(
+            parse_checker_output(
+                "/tmp/inkjet-check.1.js:3\n\n\n\nSyntaxError: Unexpected end of input\n    at wrapSafe (node:internal)\n",
+                path
+            ),
+            (3, "SyntaxError: Unexpected end of input".to_string())
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn maps_script_errors_to_markdown_lines() {
+        let diagnostics = check(
+            "## broken\n\n```sh\necho start\nif true; then\n  echo never closed\n```\n",
+            "inkjet.md",
+            true,
+        );
+        let error = diagnostics
+            .iter()
+            .find(|d| d.message.contains("sh:"))
+            .expect("syntax error should be reported");
+        assert_eq!(error.severity, Severity::Error);
+        // The error is reported after the last line of the script
+        assert_eq!(error.line, 7);
+    }
+}
@@ -6,6 +6,8 @@ use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 
+use crate::inkfile::Inkfile;
+
 /// Reads process standard input to a String
 pub fn read_stdin() -> Result<String, String> {
     let mut buffer = String::new();
@@ -17,7 +19,7 @@ pub fn read_stdin() -> Result<String, String> {
     }
 }
 /// Build a fake filename for display in "generated by" help text when stdin is read as the source
-fn stdin_name() -> String {
+pub(crate) fn stdin_name() -> String {
     let pwd = std::env::current_dir().expect("Inkjet: os did not supply working directory");
     String::from(
         pwd.join("stdin")
@@ -71,8 +73,21 @@ fn read_and_return(file: Result<std::fs::File, std::io::Error>) -> String {
     inkfile_contents
 }
 
-/// Finds an inkfile and returns its contents and inkfile_path
+/// Returns the inkfile bundled into the running executable by `--inkjet-bundle`, if any
+pub fn bundled() -> Option<Inkfile> {
+    crate::bundle::embedded()
+}
+
+/// Finds an inkfile and returns its contents and inkfile_path.
+/// If no inkfile is given and one is bundled into the running executable, the contents of its root inkfile
+/// and the path of the executable are returned. `Inkfile::load` reads the whole bundle from that path.
 pub fn find_inkfile(inkfile_opt: &str) -> (Result<String, String>, String) {
+    if inkfile_opt.is_empty()
+        && let Some(inkfile) = bundled()
+    {
+        let contents = inkfile.sources.first().map(|s| s.contents.clone());
+        return (Ok(contents.unwrap_or_default()), inkfile.path);
+    }
     let (contents, inkfile_path, is_file) = read_inkfile(inkfile_opt);
     if contents.is_err() {
         (contents, "".to_string())
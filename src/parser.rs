@@ -4,24 +4,436 @@
 use crate::utils;
 use pulldown_cmark::CodeBlockKind::Fenced;
 use pulldown_cmark::{
-    Event::{Code, End, Html, Start, Text},
+    Event::{Code, End, Html, SoftBreak, Start, Text},
     Options, Parser, Tag, TagEnd,
 };
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashSet;
 
-use crate::command::{Arg, CommandBlock, NamedFlag};
+use crate::command::{Arg, CommandBlock, NamedFlag, Script};
 
 /// Creates the message that is returned on an error
 fn invalid_type_msg(t: &str) -> String {
     format!("Invalid flag type '{t}' Expected string | number | bool.")
 }
 
+/// Location points to the place in an inkfile where a ParseError occurred.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Location {
+    /// The path of the inkfile. It is empty for the main inkfile until `ParseError::with_file` is called.
+    pub file: String,
+    /// The line number, starting at 1
+    pub line: usize,
+    /// The column in characters, starting at 1
+    pub column: usize,
+    /// The source line that contains the error
+    pub snippet: String,
+    /// The number of characters of the snippet to underline
+    pub len: usize,
+}
+
+impl Location {
+    /// Computes the location of `len` bytes at `offset` in `source`.
+    /// Lines are counted from `section_start`, the offset where the file named by the last `<!-- inkfile: -->` marker begins.
+    pub(crate) fn new(
+        source: &str,
+        section_start: usize,
+        file: &str,
+        offset: usize,
+        len: usize,
+    ) -> Location {
+        let before = source.get(..offset).unwrap_or(source);
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1).max(section_start);
+        let line_text = source.get(line_start..).unwrap_or("");
+        let snippet = line_text.lines().next().unwrap_or("").to_string();
+        let column_text = source.get(line_start..offset).unwrap_or("");
+        let column = column_text.chars().count();
+        let len = source
+            .get(offset..offset + len)
+            .map_or(1, |token| token.chars().count())
+            .clamp(1, snippet.chars().count().saturating_sub(column).max(1));
+        Location {
+            file: file.to_string(),
+            line: source
+                .get(section_start..line_start)
+                .map_or(0, |s| s.matches('\n').count())
+                + 1,
+            column: column + 1,
+            snippet,
+            len,
+        }
+    }
+}
+
+/// ParseError is returned when an inkfile cannot be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The `+++` front matter is not valid TOML or has a value of the wrong type
+    InvalidFrontMatter {
+        /// The message from the TOML parser
+        message: String,
+        /// Where the invalid value is, if the TOML parser knows
+        location: Option<Location>,
+    },
+    /// A flag has a type other than string, number or bool
+    InvalidFlagType {
+        /// The type that was given
+        kind: String,
+        /// Where the type is
+        location: Location,
+    },
+    /// A heading does not contain a command name
+    EmptyHeadingName {
+        /// Where the heading is
+        location: Location,
+    },
+    /// A command name contains whitespace
+    SpacesInName {
+        /// The command name that was found
+        name: String,
+        /// Where the command name is
+        location: Location,
+    },
+    /// A `complete` code block names something that is not an arg or flag of its command
+    UnknownCompletionTarget {
+        /// The name that was given
+        name: String,
+        /// Where the code block is
+        location: Location,
+    },
+    /// Two commands share an alias. Holds each alias that is used more than once.
+    DuplicateAliases(Vec<String>),
+    /// An imported inkfile could not be found or read, or imports would form a cycle
+    Import(String),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::InvalidFrontMatter { message, .. } => {
+                write!(f, "Invalid front matter: {message}")
+            }
+            ParseError::InvalidFlagType { kind, .. } => write!(f, "{}", invalid_type_msg(kind)),
+            ParseError::EmptyHeadingName { .. } => write!(f, "unexpected empty heading name"),
+            ParseError::SpacesInName { name, .. } => write!(
+                f,
+                "Command names cannot contain spaces. Found '{name}'. Did you forget to wrap args in ()?"
+            ),
+            ParseError::UnknownCompletionTarget { name, .. } => write!(
+                f,
+                "The complete block names '{name}', which is not an arg or flag of its command"
+            ),
+            ParseError::DuplicateAliases(aliases) => write!(
+                f,
+                "Duplicate command aliases found: {}. Please update inkjet files to remove duplicate aliases",
+                aliases.join(", ")
+            ),
+            ParseError::Import(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// Returns where in the inkfile the error occurred, if it is known.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            ParseError::InvalidFrontMatter { location, .. } => location.as_ref(),
+            ParseError::InvalidFlagType { location, .. }
+            | ParseError::EmptyHeadingName { location }
+            | ParseError::SpacesInName { location, .. }
+            | ParseError::UnknownCompletionTarget { location, .. } => Some(location),
+            ParseError::DuplicateAliases(_) | ParseError::Import(_) => None,
+        }
+    }
+
+    /// Sets the file of the error location if it was not set by an `<!-- inkfile: -->` marker.
+    #[must_use]
+    pub fn with_file(mut self, file: &str) -> Self {
+        let location = match &mut self {
+            ParseError::InvalidFrontMatter { location, .. } => location.as_mut(),
+            ParseError::InvalidFlagType { location, .. }
+            | ParseError::EmptyHeadingName { location }
+            | ParseError::SpacesInName { location, .. }
+            | ParseError::UnknownCompletionTarget { location, .. } => Some(location),
+            ParseError::DuplicateAliases(_) | ParseError::Import(_) => None,
+        };
+        if let Some(location) = location
+            && location.file.is_empty()
+        {
+            location.file = file.to_string();
+        }
+        self
+    }
+
+    /// Renders the error in the style of rustc with the offending source line and a caret under the bad token.
+    pub fn render(&self, color: bool) -> String {
+        let prefix = if color {
+            utils::error_msg()
+        } else {
+            utils::plain_msg("ERROR")
+        };
+        let Some(location) = self.location() else {
+            return format!("{prefix} {self}");
+        };
+        let line_no = location.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+        let carets = "^".repeat(location.len);
+        let carets = if color {
+            color_print::cformat!("<red><bold>{}</bold></red>", carets)
+        } else {
+            carets
+        };
+        let indent = " ".repeat(location.column.saturating_sub(1));
+        format!(
+            "{prefix} {self}\n{gutter}--> {}:{}:{}\n{gutter} |\n{line_no} | {}\n{gutter} | {indent}{carets}",
+            location.file, location.line, location.column, location.snippet
+        )
+    }
+}
+
+/// InkjetConfig holds the directives that change how an inkfile is parsed and executed.
+/// It is read from TOML front matter delimited by `+++` lines at the top of the document:
+///
+/// ```toml
+/// +++
+/// sort = true
+/// fixed_dir = false
+/// import = ["all"]
+/// +++
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InkjetConfig {
+    /// List subcommands alphabetically in help output instead of in document order.
+    pub sort: bool,
+    /// Execute scripts from the directory of the inkfile that defined them. Defaults to true.
+    pub fixed_dir: bool,
+    /// Other inkfiles to merge into this one. `all` merges every inkfile found below this one.
+    pub import: Vec<String>,
+    /// Glob patterns, relative to this inkfile's directory, of paths that `import = ["all"]` should not search.
+    pub import_exclude: Vec<String>,
+    /// How many directories deep `import = ["all"]` searches below this inkfile. Unlimited by default.
+    pub import_max_depth: Option<usize>,
+    /// The program name shown in help, completions and message prefixes instead of `inkjet`.
+    /// A bundled CLI defaults to the name of its executable.
+    pub name: Option<String>,
+    /// The version printed by `--version`. Defaults to the version of inkjet.
+    pub version: Option<String>,
+    /// The about text shown at the top of the help output. Defaults to the description below the first heading.
+    pub about: Option<String>,
+    /// The author shown in the help output
+    pub author: Option<String>,
+    /// Show a searchable list of commands when no command is given and there is no `default` command.
+    pub picker: bool,
+    /// Run the shell blocks of runbook and interactive steps in one long-lived shell, so `cd` and variables carry over.
+    pub session: bool,
+    /// Keys that inkjet does not understand. They are reported as warnings.
+    #[serde(flatten)]
+    unknown: toml::Table,
+}
+
+impl Default for InkjetConfig {
+    fn default() -> Self {
+        Self {
+            sort: false,
+            fixed_dir: true,
+            import: vec![],
+            import_exclude: vec![],
+            import_max_depth: None,
+            name: None,
+            version: None,
+            about: None,
+            author: None,
+            picker: false,
+            session: false,
+            unknown: toml::Table::new(),
+        }
+    }
+}
+
+/// Reads the InkjetConfig for an inkfile from its front matter.
+/// The inline directives (i.e. `inkjet_sort: true`) are still read when no front matter exists,
+/// but a deprecation notice is printed for each of them if `log_warnings` is set.
+pub fn parse_config(
+    inkfile_contents: &str,
+    log_warnings: bool,
+) -> Result<InkjetConfig, ParseError> {
+    let (config, warnings) = read_config(inkfile_contents)?;
+    if log_warnings {
+        for warning in warnings {
+            eprintln!("{} {warning}", utils::warning_msg());
+        }
+    }
+    Ok(config)
+}
+
+/// Reads the InkjetConfig for an inkfile like `parse_config`, but returns the warnings about
+/// unknown front matter keys and deprecated or ignored inline directives instead of printing them.
+pub fn read_config(inkfile_contents: &str) -> Result<(InkjetConfig, Vec<String>), ParseError> {
+    let legacy = find_inline_directives(inkfile_contents);
+    let mut warnings = vec![];
+    let Some((toml_start, toml_src)) = front_matter(inkfile_contents) else {
+        let mut config = InkjetConfig::default();
+        for (key, val) in legacy {
+            let replacement = match (key.as_str(), val.as_str()) {
+                ("inkjet_sort", "true" | "false") => {
+                    config.sort = val == "true";
+                    format!("sort = {val}")
+                }
+                ("inkjet_fixed_dir", "true" | "false") => {
+                    config.fixed_dir = val == "true";
+                    format!("fixed_dir = {val}")
+                }
+                ("inkjet_import", _) => {
+                    config.import.push(val.clone());
+                    format!("import = [\"{val}\"]")
+                }
+                _ => continue,
+            };
+            warnings.push(format!(
+                "The inline directive \"{key}: {val}\" is deprecated. Use \"{replacement}\" in the +++ front matter instead."
+            ));
+        }
+        return Ok((config, warnings));
+    };
+
+    let config: InkjetConfig =
+        toml::from_str(toml_src).map_err(|e| ParseError::InvalidFrontMatter {
+            message: e.message().to_string(),
+            location: e.span().map(|span| {
+                Location::new(
+                    inkfile_contents,
+                    0,
+                    "",
+                    toml_start + span.start,
+                    span.end - span.start,
+                )
+            }),
+        })?;
+    for key in config.unknown.keys() {
+        warnings.push(format!("Unknown front matter key \"{key}\" was ignored"));
+    }
+    for (key, val) in legacy {
+        warnings.push(format!(
+            "The inline directive \"{key}: {val}\" was ignored because the document has front matter"
+        ));
+    }
+    Ok((config, warnings))
+}
+
+/// Returns the offset and TOML source between the `+++` front matter delimiters if the document starts with them.
+/// A shebang line is allowed before the front matter so that inkfiles can remain executable.
+fn front_matter(inkfile_contents: &str) -> Option<(usize, &str)> {
+    let mut lines = inkfile_contents.split_inclusive('\n').peekable();
+    let mut offset = 0;
+    if lines.peek().is_some_and(|line| line.starts_with("#!")) {
+        offset += lines.next().map_or(0, str::len);
+    }
+    let opening = lines.next()?;
+    if opening.trim() != "+++" {
+        return None;
+    }
+    offset += opening.len();
+    let start = offset;
+    for line in lines {
+        if line.trim() == "+++" {
+            return inkfile_contents.get(start..offset).map(|src| (start, src));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Returns the byte range of the front matter including its `+++` delimiters if the document starts with it.
+pub(crate) fn front_matter_range(inkfile_contents: &str) -> Option<std::ops::Range<usize>> {
+    let (start, src) = front_matter(inkfile_contents)?;
+    let opening = inkfile_contents
+        .get(..start - 1)?
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    let closing = start + src.len();
+    let end = inkfile_contents
+        .get(closing..)?
+        .find('\n')
+        .map_or(inkfile_contents.len(), |i| closing + i + 1);
+    Some(opening..end)
+}
+
+/// Replaces the front matter with spaces so that its TOML is not read as markdown.
+/// Line breaks are kept, so offsets and line numbers still match the original document.
+pub fn blank_front_matter(inkfile_contents: &str) -> Cow<'_, str> {
+    let Some(range) = front_matter_range(inkfile_contents) else {
+        return Cow::Borrowed(inkfile_contents);
+    };
+    let mut blanked = inkfile_contents.to_string();
+    let spaces: String = inkfile_contents
+        .get(range.clone())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c {
+            '\n' => "\n".to_string(),
+            // A multi-byte character becomes as many spaces as it has bytes
+            c => " ".repeat(c.len_utf8()),
+        })
+        .collect();
+    blanked.replace_range(range, &spaces);
+    Cow::Owned(blanked)
+}
+
+/// Finds paragraphs in the form of `inkjet_<key>: <value>`. These inline directives predate front matter.
+/// Code blocks are never searched so that scripts can mention directives without enabling them.
+fn find_inline_directives(inkfile_contents: &str) -> Vec<(String, String)> {
+    let mut directives = vec![];
+    let mut paragraph = String::new();
+    let mut in_paragraph = false;
+    for event in create_markdown_parser(inkfile_contents) {
+        match event {
+            Start(Tag::Paragraph) => {
+                in_paragraph = true;
+                paragraph.clear();
+            }
+            End(TagEnd::Paragraph) => {
+                in_paragraph = false;
+                for line in paragraph.lines() {
+                    if let Some((key, val)) = line.trim().split_once(':')
+                        && key.starts_with("inkjet_")
+                        && !key.contains(char::is_whitespace)
+                    {
+                        directives.push((key.to_string(), val.trim().to_string()));
+                    }
+                }
+            }
+            Text(body) if in_paragraph => paragraph.push_str(&body),
+            SoftBreak if in_paragraph => paragraph.push('\n'),
+            _ => (),
+        }
+    }
+    directives
+}
+
 /// The main inkjet markdown parsing logic. Takes an inkfile content as a string and returns the parsed CommandBlock tree.
 pub fn build_command_structure(
     inkfile_contents: &str,
     log_warnings: bool,
-) -> Result<CommandBlock, String> {
+) -> Result<CommandBlock, ParseError> {
+    parse_document(inkfile_contents, "", log_warnings).map(|(root, _)| root)
+}
+
+/// Parses an inkfile into its CommandBlock tree along with the commands that were dropped from it
+/// because they have neither a code block nor subcommands. `file` is the path of the inkfile, used in merge warnings.
+// The event arms read as the markdown structure they handle, so their conditions stay inside them
+#[allow(clippy::collapsible_match)]
+pub(crate) fn parse_document(
+    inkfile_contents: &str,
+    file: &str,
+    log_warnings: bool,
+) -> Result<(CommandBlock, Vec<CommandBlock>), ParseError> {
+    let blanked = blank_front_matter(inkfile_contents);
+    let inkfile_contents = blanked.as_ref();
     let parser = create_markdown_parser(inkfile_contents);
     let mut commands = vec![];
     let mut current_command = CommandBlock::new(1);
@@ -30,9 +442,39 @@ pub fn build_command_structure(
     #[cfg(not(windows))]
     let mut current_lc = "".to_string();
     let mut list_level = 0;
-    let mut first_was_pushed = false;
+    let mut seen_heading = false;
+    let mut in_title = false;
     let mut current_file = "".to_string();
+    // The offset where the document named by current_file starts. Used to compute line numbers.
+    let mut section_start = 0;
     let mut in_block_quote = false;
+    // The arg or flag name, script and range of a `complete` code block being read
+    let mut completion: Option<(String, Script, std::ops::Range<usize>)> = None;
+    // Computes the location of an error in the inkfile from an offset range
+    let locate = |file: &str, section_start: usize, range: std::ops::Range<usize>| {
+        Location::new(
+            inkfile_contents,
+            section_start,
+            file,
+            range.start,
+            range.len(),
+        )
+    };
+    // Finds a token within an event's range so the error can point at it
+    let locate_token =
+        |file: &str, section_start: usize, range: std::ops::Range<usize>, token: &str| {
+            let found = inkfile_contents
+                .get(range.clone())
+                .and_then(|src| src.find(token));
+            match found {
+                Some(pos) => locate(
+                    file,
+                    section_start,
+                    range.start + pos..range.start + pos + token.len(),
+                ),
+                None => locate(file, section_start, range),
+            }
+        };
 
     for (event, range) in parser.into_offset_iter() {
         match event {
@@ -40,16 +482,29 @@ pub fn build_command_structure(
                 match tag {
                     Tag::Heading { level, .. } => {
                         let heading_level = level as u8;
-                        // Add the last command before starting a new one.
-                        // Don't add the first command during the first iteration.
-                        if heading_level > 1 || first_was_pushed {
-                            first_was_pushed = true;
+                        // A H1 before any other heading is the document title. It describes the root command.
+                        in_title = heading_level == 1 && !seen_heading;
+                        seen_heading = true;
+                        if !in_title {
+                            // Add the last command before starting a new one.
                             commands.push(current_command.build());
+                            current_command = CommandBlock::new(heading_level);
                         }
-                        current_command = CommandBlock::new(heading_level);
                         current_command.inkjet_file = current_file.clone();
                         current_command.start = range.start;
                     }
+                    // A `sh complete <name>` block lists the values of an arg or flag when completing
+                    Tag::CodeBlock(Fenced(lang_code))
+                        if completion_target(&lang_code).is_some() =>
+                    {
+                        if let Some((executor, target)) = completion_target(&lang_code) {
+                            let mut script = Script::new();
+                            script.executor = executor.to_string();
+                            (script.offset, script.line) =
+                                script_position(inkfile_contents, section_start, range.start);
+                            completion = Some((target.to_string(), script, range.clone()));
+                        }
+                    }
                     #[cfg(not(windows))]
                     Tag::CodeBlock(Fenced(lang_code)) => {
                         current_lc = lang_code.to_string();
@@ -59,12 +514,16 @@ pub fn build_command_structure(
                         {
                             current_command.end = range.start;
                             current_command.script.executor = current_lc.clone();
+                            (current_command.script.offset, current_command.script.line) =
+                                script_position(inkfile_contents, section_start, range.start);
                         }
                     }
                     #[cfg(windows)]
                     Tag::CodeBlock(Fenced(lang_code)) => {
                         current_command.end = range.start;
                         current_command.script.executor = lang_code.to_string();
+                        (current_command.script.offset, current_command.script.line) =
+                            script_position(inkfile_contents, section_start, range.start);
                     }
                     Tag::List(_) => {
                         // We're in an options list if the current text above it is "OPTIONS"
@@ -84,19 +543,39 @@ pub fn build_command_structure(
             End(tag) => match tag {
                 TagEnd::Heading(level) => {
                     let heading_level = level as u8;
-                    let mut virtual_heading_level = heading_level;
-                    if first_was_pushed && heading_level == 1 {
-                        virtual_heading_level = 2; // This case occurs during a merge
-                    }
-                    let (name, aliases, args) =
-                        parse_heading_to_cmd(virtual_heading_level, text.clone());
+                    let heading = match text.trim_end().strip_suffix("{override}") {
+                        Some(heading) => {
+                            current_command.overrides = true;
+                            heading.trim_end().to_string()
+                        }
+                        None => text.clone(),
+                    };
+                    let (name, aliases, args) = if in_title {
+                        // Only the first word of the title names the root command
+                        let title = heading.split_whitespace().next().unwrap_or("").to_string();
+                        parse_heading_to_cmd(0, title)
+                    } else {
+                        // "### db flush" is named "flush" because "db" names its parent.
+                        // H1 and H2 headings name a command of their own.
+                        parse_heading_to_cmd(heading_level.saturating_sub(2) as usize, heading)
+                    };
                     if name.is_empty() {
-                        return Err("unexpected empty heading name".to_string());
+                        return Err(ParseError::EmptyHeadingName {
+                            location: locate(&current_file, section_start, range),
+                        });
                     }
                     if name.contains(char::is_whitespace) {
-                        return Err(format!(
-                            "Command names cannot contain spaces. Found '{name}'. Did you forget to wrap args in ()?"
-                        ));
+                        // Names are lowercased so search for the first word of the name without case
+                        let first_word = name.split_whitespace().next().unwrap_or("");
+                        let heading_src = inkfile_contents.get(range.clone()).unwrap_or("");
+                        let location = match heading_src.to_lowercase().find(first_word) {
+                            Some(pos) if heading_src.is_char_boundary(pos) => {
+                                let start = range.start + pos;
+                                locate(&current_file, section_start, start..start + name.len())
+                            }
+                            _ => locate(&current_file, section_start, range),
+                        };
+                        return Err(ParseError::SpacesInName { name, location });
                     }
                     current_command.name = name;
                     current_command.args = args;
@@ -109,6 +588,29 @@ pub fn build_command_structure(
                         in_block_quote = false;
                     }
                 }
+                TagEnd::CodeBlock if completion.is_some() => {
+                    if let Some((target, mut script, range)) = completion.take() {
+                        script.source = text.to_string();
+                        let cmd = &mut current_command;
+                        if let Some(arg) = cmd.args.iter_mut().find(|a| a.name == target) {
+                            arg.complete = Some(script);
+                        } else if let Some(flag) =
+                            cmd.named_flags.iter_mut().find(|f| f.name == target)
+                        {
+                            flag.complete = Some(script);
+                        } else {
+                            return Err(ParseError::UnknownCompletionTarget {
+                                location: locate_token(
+                                    &current_file,
+                                    section_start,
+                                    range,
+                                    &target,
+                                ),
+                                name: target,
+                            });
+                        }
+                    }
+                }
                 #[cfg(not(windows))]
                 TagEnd::CodeBlock => {
                     if current_lc != "powershell" && current_lc != "batch" && current_lc != "cmd" {
@@ -141,13 +643,37 @@ pub fn build_command_structure(
 
                 // Options level 1 is the flag name
                 if list_level == 1 {
-                    if text.contains(':') {
+                    if let Some((key @ ("confirm" | "confirm_phrase"), val)) =
+                        text.split_once(':').map(|(key, val)| (key.trim(), val))
+                    {
+                        // Command options ask before the command runs. They are not flags.
+                        let val = val.trim().trim_matches('"').to_string();
+                        if key == "confirm" {
+                            current_command.confirm = val;
+                        } else {
+                            current_command.confirm_phrase = val;
+                        }
+                    } else if text.contains(':') {
                         // Shorthand syntax
                         let mut flag_split = text.splitn(2, ':');
                         if flag_split.next().unwrap_or("").trim() == "flag" {
                             let val = flag_split.next().unwrap_or("").trim();
                             let mut desc_words = String::with_capacity(val.len());
+                            let mut words: Vec<String> = Vec::new();
                             for word in val.split_whitespace() {
+                                match words.last_mut() {
+                                    // A type with modifiers such as |string secret| spans several words
+                                    Some(last)
+                                        if last.starts_with('|')
+                                            && (last.len() == 1 || !last.ends_with('|')) =>
+                                    {
+                                        last.push(' ');
+                                        last.push_str(word);
+                                    }
+                                    _ => words.push(word.to_string()),
+                                }
+                            }
+                            for word in words.iter().map(String::as_str) {
                                 if word.starts_with("--") {
                                     let name = word.split("--").collect::<Vec<&str>>().join("");
                                     current_named_flag.long = name.clone();
@@ -159,10 +685,24 @@ pub fn build_command_structure(
                                     let name = word.get(1..2).unwrap_or("");
                                     current_named_flag.short = name.to_string();
                                 } else if word.starts_with('|') && word.ends_with('|') {
-                                    let mut kind = word.to_string();
-                                    kind.pop();
-                                    kind.remove(0);
-                                    match kind.as_str() {
+                                    let inner = word.trim_matches('|');
+                                    let mut parts = inner.split_whitespace();
+                                    let kind = parts.next().unwrap_or("");
+                                    for modifier in parts {
+                                        if modifier != "secret" {
+                                            return Err(ParseError::InvalidFlagType {
+                                                kind: inner.to_string(),
+                                                location: locate_token(
+                                                    &current_file,
+                                                    section_start,
+                                                    range,
+                                                    word,
+                                                ),
+                                            });
+                                        }
+                                        current_named_flag.secret = true;
+                                    }
+                                    match kind {
                                         "string" => {
                                             current_named_flag.takes_value = true;
                                         }
@@ -172,7 +712,15 @@ pub fn build_command_structure(
                                         }
                                         "bool" | "boolean" => {}
                                         t => {
-                                            return Err(invalid_type_msg(t));
+                                            return Err(ParseError::InvalidFlagType {
+                                                kind: t.to_string(),
+                                                location: locate_token(
+                                                    &current_file,
+                                                    section_start,
+                                                    range,
+                                                    word,
+                                                ),
+                                            });
                                         }
                                     }
                                 } else if word == "required" {
@@ -206,7 +754,15 @@ pub fn build_command_structure(
                                     current_named_flag.validate_as_number = true;
                                 }
                             } else {
-                                return Err(invalid_type_msg(val));
+                                return Err(ParseError::InvalidFlagType {
+                                    kind: val.to_string(),
+                                    location: locate_token(
+                                        &current_file,
+                                        section_start,
+                                        range,
+                                        val,
+                                    ),
+                                });
                             }
                         }
                         // Parse out the short and long flag names
@@ -235,6 +791,14 @@ pub fn build_command_structure(
                         "required" => {
                             current_named_flag.required = true;
                         }
+                        "secret" => {
+                            current_named_flag.secret = val != "false";
+                        }
+                        "complete" => {
+                            let mut script = Script::new();
+                            script.source = val.to_string();
+                            current_named_flag.complete = Some(script);
+                        }
                         _ => (),
                     };
                 }
@@ -246,7 +810,8 @@ pub fn build_command_structure(
                 // as it's not self documenting (comments are hidden when rendered).
                 let s = "<!-- inkfile: ";
                 if html.starts_with(s) {
-                    current_file = html.replace(s, "").replace(" -->", "");
+                    current_file = html.replace(s, "").replace(" -->", "").trim().to_string();
+                    section_start = range.end;
                 }
                 text += html.as_ref();
             }
@@ -265,76 +830,188 @@ pub fn build_command_structure(
     commands.push(current_command.build());
 
     // Convert the flat commands array and to a tree of subcommands based on level
-    let all = treeify_commands(commands);
-    let all = remove_duplicates(all, log_warnings);
-    let root_command = all.first().expect("Inkjet: root command must exist");
-    let has_duplicate_aliases = validate_no_duplicate_aliases(root_command.clone());
-    if has_duplicate_aliases {
-        return Err("Please update inkjet files to remove duplicate aliases".to_string());
-    }
-    // The command root
-    Ok(root_command.clone())
-}
-
-fn validate_no_duplicate_aliases(cmd: CommandBlock) -> bool {
-    let mut duplicates_found = false;
-    let mut seen_aliases: HashSet<String> = HashSet::new();
-    let mut errors: Vec<String> = Vec::new();
-
-    if !cmd.subcommands.is_empty() {
-        for subcommand in cmd.subcommands {
-            let aliases = subcommand.aliases.split("//");
-            for alias in aliases {
-                if seen_aliases.contains(alias) {
-                    duplicates_found = true;
-                    errors.push(alias.to_string());
-                    eprintln!(
-                        "{} Duplicate command alias found: {}",
-                        utils::ERROR_MSG,
-                        alias
-                    );
-                } else if !alias.is_empty() {
-                    seen_aliases.insert(alias.to_string());
+    let (root_command, removed) = treeify_commands(commands);
+    Ok((finalize_tree(root_command, file, log_warnings)?, removed))
+}
+
+/// Returns the executor and the arg or flag name of a code block fenced with i.e. ` ```sh complete branch `.
+fn completion_target(lang_code: &str) -> Option<(&str, &str)> {
+    let mut words = lang_code.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("complete"), Some(target), None) => Some(("", target)),
+        (Some(executor), Some("complete"), Some(target)) => Some((executor, target)),
+        _ => None,
+    }
+}
+
+/// Returns the byte offset and line number of the first line of a code block's source, which follows the opening fence.
+fn script_position(inkfile_contents: &str, section_start: usize, fence: usize) -> (usize, usize) {
+    let fence_line = Location::new(inkfile_contents, section_start, "", fence, 0).line;
+    let offset = inkfile_contents
+        .get(fence..)
+        .and_then(|rest| rest.find('\n'))
+        .map_or(inkfile_contents.len(), |i| fence + i + 1);
+    (offset, fence_line + 1)
+}
+
+/// Merges duplicate commands and checks a CommandBlock tree for duplicate aliases.
+/// This is called again once imported inkfiles are mounted so that definitions can override each other across files.
+/// Commands without an `inkjet_file` belong to root_file.
+pub(crate) fn finalize_tree(
+    mut root_command: CommandBlock,
+    root_file: &str,
+    log_warnings: bool,
+) -> Result<CommandBlock, ParseError> {
+    let merge = Merge {
+        root_file,
+        log_warnings,
+    };
+    root_command.subcommands = merge.duplicates(root_command.subcommands);
+    let duplicates = duplicate_aliases(&root_command);
+    if !duplicates.is_empty() {
+        return Err(ParseError::DuplicateAliases(duplicates));
+    }
+    Ok(root_command)
+}
+
+/// Returns every alias that is used by more than one command with the same parent
+fn duplicate_aliases(cmd: &CommandBlock) -> Vec<String> {
+    let mut duplicates = vec![];
+    let mut seen_aliases: HashSet<&str> = HashSet::new();
+    for subcommand in &cmd.subcommands {
+        for alias in subcommand.aliases.split("//") {
+            if seen_aliases.contains(alias) {
+                duplicates.push(alias.to_string());
+            } else if !alias.is_empty() {
+                seen_aliases.insert(alias);
+            }
+        }
+        duplicates.extend(duplicate_aliases(subcommand));
+    }
+    duplicates
+}
+
+/// Merge combines the definitions of commands that share a name
+struct Merge<'a> {
+    /// The path of the inkfile that commands without an `inkjet_file` belong to
+    root_file: &'a str,
+    log_warnings: bool,
+}
+
+impl Merge<'_> {
+    /// Merges commands that share a name so that later definitions extend earlier ones.
+    /// Subcommands are combined, flags and aliases are unioned, and a later description or script wins.
+    /// A command with an `{override}` marker replaces the earlier definition entirely.
+    fn duplicates(&self, cmds: Vec<CommandBlock>) -> Vec<CommandBlock> {
+        let mut merged: Vec<CommandBlock> = vec![];
+        for cmd in cmds {
+            match merged.iter_mut().find(|c| c.name == cmd.name) {
+                Some(existing) if cmd.overrides => {
+                    if self.log_warnings {
+                        eprintln!(
+                            "{} Command \"{}\" in {} overrides the definition in {}",
+                            utils::info_msg(),
+                            cmd.name,
+                            self.source_name(&cmd.inkjet_file),
+                            self.source_name(&existing.inkjet_file)
+                        );
+                    }
+                    *existing = cmd;
                 }
+                Some(existing) => self.command(existing, cmd),
+                None => merged.push(cmd),
             }
-            if !subcommand.subcommands.is_empty() {
-                duplicates_found = validate_no_duplicate_aliases(subcommand)
+        }
+        for c in &mut merged {
+            if !c.subcommands.is_empty() {
+                c.subcommands = self.duplicates(std::mem::take(&mut c.subcommands));
             }
         }
+        merged
     }
-    duplicates_found
-}
-
-// remove duplicate commands to enable override function
-fn remove_duplicates(mut cmds: Vec<CommandBlock>, log_warnings: bool) -> Vec<CommandBlock> {
-    fn clear_duplicates(cmds: &mut Vec<CommandBlock>, log_warnings: bool) {
-        let mut already_seen = vec![];
-        cmds.retain(|item| {
-            if already_seen.contains(item) {
-                if log_warnings {
-                    eprintln!(
-                        "{} Duplicate command overwritten: {}",
-                        utils::INFO_MSG,
-                        item.name
-                    );
+
+    /// Merges a later definition of a command into an earlier one
+    fn command(&self, existing: &mut CommandBlock, cmd: CommandBlock) {
+        let previous_file = self.source_name(&existing.inkjet_file).to_string();
+        let file = self.source_name(&cmd.inkjet_file).to_string();
+        let report = |what: &str| {
+            if self.log_warnings {
+                eprintln!(
+                    "{} Command \"{}\" in {file} replaces the {what} defined in {previous_file}",
+                    utils::info_msg(),
+                    cmd.name,
+                );
+            }
+        };
+        if !cmd.script.source.is_empty() {
+            if !existing.script.source.is_empty() {
+                report("script");
+            }
+            // The location of a command follows its script so that it runs from the directory of its inkfile
+            existing.script = cmd.script.clone();
+            existing.start = cmd.start;
+            existing.end = cmd.end;
+            existing.inkjet_file = cmd.inkjet_file.clone();
+        }
+        if !cmd.args.is_empty() {
+            if !existing.args.is_empty() {
+                report("args");
+            }
+            existing.args = cmd.args.clone();
+        }
+        if !cmd.desc.is_empty() {
+            if !existing.desc.is_empty() && existing.desc != cmd.desc {
+                report("description");
+            }
+            existing.desc = cmd.desc.clone();
+        }
+        if !cmd.confirm.is_empty() {
+            if !existing.confirm.is_empty() && existing.confirm != cmd.confirm {
+                report("confirm option");
+            }
+            existing.confirm = cmd.confirm.clone();
+        }
+        if !cmd.confirm_phrase.is_empty() {
+            if !existing.confirm_phrase.is_empty() && existing.confirm_phrase != cmd.confirm_phrase
+            {
+                report("confirm_phrase option");
+            }
+            existing.confirm_phrase = cmd.confirm_phrase.clone();
+        }
+        for alias in cmd.aliases.split("//").filter(|a| !a.is_empty()) {
+            if !existing.aliases.split("//").any(|a| a == alias) {
+                if !existing.aliases.is_empty() {
+                    existing.aliases.push_str("//");
                 }
-                false
-            } else {
-                already_seen.push(item.clone());
-                true
+                existing.aliases.push_str(alias);
             }
-        });
+        }
+        for flag in &cmd.named_flags {
+            match existing
+                .named_flags
+                .iter_mut()
+                .find(|f| f.name == flag.name)
+            {
+                Some(f) => {
+                    if flag.name != "verbose" {
+                        report(&format!("flag \"{}\"", flag.name));
+                    }
+                    *f = flag.clone();
+                }
+                None => existing.named_flags.push(flag.clone()),
+            }
+        }
+        existing.subcommands.extend(cmd.subcommands);
     }
 
-    cmds.reverse();
-    clear_duplicates(&mut cmds, log_warnings);
-    cmds.reverse();
-    for c in &mut cmds {
-        if !c.subcommands.is_empty() {
-            c.subcommands = remove_duplicates(c.subcommands.clone(), log_warnings);
+    /// Returns the path of the inkfile a command was defined in
+    fn source_name<'a>(&'a self, inkjet_file: &'a str) -> &'a str {
+        match (inkjet_file, self.root_file) {
+            ("", "") => "the main inkfile",
+            ("", root_file) => root_file,
+            (file, _) => file,
         }
     }
-    cmds
 }
 
 fn create_markdown_parser(inkfile_contents: &'_ str) -> Parser<'_> {
@@ -356,82 +1033,116 @@ fn trim_and_remove_options(input: &str) -> String {
     }
 }
 
-/// `treeify_commands` takes a flat vector of CommandBlocks and recursively builds a tree with subcommands as children.
-/// It is called by the parser.
-fn treeify_commands(commands: Vec<CommandBlock>) -> Vec<CommandBlock> {
-    let mut command_tree = vec![];
-    let mut current_command = commands.first().expect("command should exist").clone();
-    let num_commands = commands.len();
-    let mut add = 0;
-    let mut allow_increment = false;
-
-    for i in 0..num_commands {
-        let mut c = commands.get(i).unwrap().clone();
-        let is_last_cmd = i == num_commands - 1;
+/// TreeNode is a CommandBlock in the arena built by `treeify_commands`.
+struct TreeNode {
+    cmd: CommandBlock,
+    /// The markdown heading level that defined this command. The root is level 0.
+    heading_level: u8,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
 
-        c.desc = trim_and_remove_options(&c.desc);
+/// CommandArena holds the commands of a document while they are linked to their parents.
+struct CommandArena {
+    nodes: Vec<TreeNode>,
+    /// Commands that were dropped because they have no script or subcommands
+    removed: Vec<CommandBlock>,
+}
 
-        // We allow virtually increasing the command level if multiple h1s are found with subcommands
-        // This enables us to cat multiple inkjet.md files together and have it function as a larger CLI
-        if c.cmd_level > 1 {
-            allow_increment = true;
-        }
-        if allow_increment && c.cmd_level == 1 {
-            add = 1;
-        }
-        c.cmd_level += add;
+impl CommandArena {
+    fn node(&self, idx: usize) -> &TreeNode {
+        self.nodes.get(idx).expect("Inkjet: tree node must exist")
+    }
 
-        // This must be a subcommand
-        if c.cmd_level > current_command.cmd_level {
-            current_command.subcommands.push(c.clone());
-        }
-        // This must be a sibling command
-        // Make sure the initial command doesn't skip itself before it finds children
-        else if c.cmd_level == current_command.cmd_level && i > 0 {
-            // Found a sibling, so the current command has found all children.
-            command_tree.push(current_command.clone());
-            current_command = c.clone();
-        }
+    fn node_mut(&mut self, idx: usize) -> &mut TreeNode {
+        self.nodes
+            .get_mut(idx)
+            .expect("Inkjet: tree node must exist")
+    }
 
-        // The last command needs to be added regardless, since there's not another iteration of the loop to do so
-        if is_last_cmd && c.cmd_level >= current_command.cmd_level {
-            command_tree.push(current_command.clone());
+    /// Moves a node and its descendants out of the arena into a CommandBlock tree.
+    /// Commands without a script or subcommands are dropped since they cannot be called.
+    fn take(&mut self, idx: usize, cmd_level: u8) -> CommandBlock {
+        let node = self.node_mut(idx);
+        let mut cmd = std::mem::replace(&mut node.cmd, CommandBlock::new(cmd_level));
+        let children = std::mem::take(&mut node.children);
+        cmd.cmd_level = cmd_level;
+        for child in children {
+            let sub = self.take(child, cmd_level + 1);
+            if sub.script.has_script() || !sub.subcommands.is_empty() {
+                cmd.subcommands.push(sub);
+            } else {
+                self.removed.push(sub);
+            }
         }
+        cmd
     }
+}
+
+/// `treeify_commands` takes a flat vector of CommandBlocks, in document order, and builds a tree with subcommands as children.
+/// The first CommandBlock is the root. Each following command is linked to the nearest previous command of a lower heading level,
+/// so every H1 after the title becomes a namespace with its own subtree of any depth.
+/// Each imported document (marked by an `<!-- inkfile: -->` comment) starts again from the root.
+/// It is called by the parser and also returns the commands that were dropped because they cannot be called.
+fn treeify_commands(commands: Vec<CommandBlock>) -> (CommandBlock, Vec<CommandBlock>) {
+    let mut commands = commands.into_iter();
+    let mut root = commands.next().expect("Inkjet: root command must exist");
+    root.desc = trim_and_remove_options(&root.desc);
+    let mut current_file = root.inkjet_file.clone();
+    let mut arena = CommandArena {
+        nodes: vec![TreeNode {
+            cmd: root,
+            heading_level: 0,
+            parent: None,
+            children: vec![],
+        }],
+        removed: vec![],
+    };
+    let mut current = 0;
 
-    // Treeify all subcommands recursively
-    for c in &mut command_tree {
-        if !c.subcommands.is_empty() {
-            c.subcommands = treeify_commands(c.subcommands.clone());
+    for mut c in commands {
+        c.desc = trim_and_remove_options(&c.desc);
+        if c.inkjet_file != current_file {
+            current_file = c.inkjet_file.clone();
+            current = 0;
+        }
+        // Walk up from the previous command until we find the heading this one is nested under.
+        let heading_level = c.cmd_level;
+        while arena.node(current).heading_level >= heading_level {
+            current = arena.node(current).parent.unwrap_or(0);
         }
+        let idx = arena.nodes.len();
+        arena.nodes.push(TreeNode {
+            cmd: c,
+            heading_level,
+            parent: Some(current),
+            children: vec![],
+        });
+        arena.node_mut(current).children.push(idx);
+        current = idx;
     }
 
-    // the command or any one of its subcommands must have script to be included in the tree
     // root level commands must be retained
-    command_tree.retain(|c| c.script.has_script() || !c.subcommands.is_empty() || c.cmd_level == 1);
-
-    command_tree
+    let root = arena.take(0, 1);
+    (root, arena.removed)
 }
 
-fn parse_heading_to_cmd(heading_level: u8, text: String) -> (String, String, Vec<Arg>) {
+/// Parses a heading into the command name, aliases and args.
+/// `parent_words` is the number of leading words that name the parent commands.
+fn parse_heading_to_cmd(parent_words: usize, text: String) -> (String, String, Vec<Arg>) {
     // Anything after double dash is handled later -- if defined, it is the last arg
     let mut parts = text.split(" -- ");
     let main_text = parts.next().unwrap();
-    // Why heading_level > 2? Because level 1 is the root command title (unused)
-    // and level 2 can't be a subcommand so no need to split.
-    let name = if heading_level > 2 {
+    let name = if parent_words > 0 {
         // Takes a subcommand name like this:
         // "#### db flush postgres (arg_name)"
         // and returns "postgres (arg_name)" as the actual name
         main_text
             .split_whitespace()
+            // Get subcommand after the parent command names
+            .skip(parent_words)
             .collect::<Vec<&str>>()
-            // Get subcommand after the parent command name
-            .split_at(heading_level as usize - 2)
-            .1
             .join(" ")
-    } else if heading_level == 1 {
-        main_text.split_whitespace().next().unwrap().to_string()
     } else {
         main_text.to_string()
     };
@@ -621,20 +1332,137 @@ echo "the string is $str"
             .find(|cmd| cmd.name == "string")
             .expect("string command missing");
         assert_eq!(string_command.name, "string");
-        assert!(
-            string_command
-                .named_flags
-                .first()
-                .expect("named flag not attached")
-                .takes_value
-        );
-        assert!(
-            !string_command
-                .named_flags
-                .first()
-                .expect("named flag not attached")
-                .validate_as_number
+        let str_flag = string_command
+            .named_flags
+            .iter()
+            .find(|flag| flag.name == "str")
+            .expect("named flag not attached");
+        assert!(str_flag.takes_value);
+        assert!(!str_flag.validate_as_number);
+    }
+
+    #[test]
+    fn merges_duplicate_commands() {
+        let tree = build_command_structure(
+            r#"
+## services//svc
+
+> Manage services
+
+### services start
+
+```
+echo "start"
+```
+
+## services//s
+
+**OPTIONS**
+
+- flag: -a --all Apply to every service
+
+### services stop
+
+```
+echo "stop"
+```
+"#,
+            false,
+        )
+        .expect("build tree failed");
+        assert_eq!(tree.subcommands.len(), 1);
+        let services = tree.subcommands.first().expect("services missing");
+        assert_eq!(services.desc, "Manage services");
+        assert_eq!(services.aliases, "svc//s");
+        assert!(services.named_flags.iter().any(|f| f.name == "all"));
+        let names: Vec<&str> = services
+            .subcommands
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["start", "stop"]);
+    }
+
+    #[test]
+    fn override_marker_replaces_command() {
+        let tree = build_command_structure(
+            r#"
+## build
+
+> Build everything
+
+### build docs
+
+```
+echo "docs"
+```
+
+## build {override}
+
+```
+echo "custom build"
+```
+"#,
+            false,
+        )
+        .expect("build tree failed");
+        let build = tree.subcommands.first().expect("build missing");
+        assert_eq!(build.name, "build");
+        assert!(build.subcommands.is_empty());
+        assert!(build.desc.is_empty());
+        assert_eq!(build.script.source, "echo \"custom build\"\n");
+    }
+
+    #[test]
+    fn nests_h1_documents_as_namespaces() {
+        let tree = build_command_structure(
+            r#"# main
+
+## build
+
+```
+echo "build"
+```
+
+# deploy
+
+> Deploy the project
+
+## prod
+
+### prod db
+
+#### prod db migrate
+
+```
+echo "migrate"
+```
+"#,
+            false,
+        )
+        .expect("build tree failed");
+        assert_eq!(tree.name, "main");
+        let deploy = tree
+            .subcommands
+            .iter()
+            .find(|c| c.name == "deploy")
+            .expect("deploy namespace missing");
+        assert_eq!(deploy.desc, "Deploy the project");
+        assert_eq!(deploy.cmd_level, 2);
+        let prod = deploy.subcommands.first().expect("prod missing");
+        let db = prod.subcommands.first().expect("db missing");
+        let migrate = db.subcommands.first().expect("migrate missing");
+        assert_eq!(migrate.name, "migrate");
+        assert_eq!(migrate.cmd_level, 5);
+    }
+
+    #[test]
+    fn errors_on_namespace_with_spaces() {
+        let result = build_command_structure(
+            "# main\n\n## a\n\n```\necho a\n```\n\n# second title\n\n## b\n\n```\necho b\n```\n",
+            false,
         );
+        assert!(result.is_err());
     }
 
     #[test]
@@ -660,10 +1488,10 @@ echo "the string is $str"
             true,
         );
         assert!(result.is_err());
-        if let Err(ref message) = result {
+        if let Err(ref err) = result {
             assert_eq!(
-                message,
-                "Please update inkjet files to remove duplicate aliases"
+                err.to_string(),
+                "Duplicate command aliases found: default. Please update inkjet files to remove duplicate aliases"
             );
         }
     }
@@ -706,7 +1534,8 @@ echo "abc"
         let tree_result = build_command_structure(file, true);
         if let Err(e) = tree_result {
             assert!(
-                e == "Command names cannot contain spaces. Found 'b c'. Did you forget to wrap args in ()?",
+                e.to_string()
+                    == "Command names cannot contain spaces. Found 'b c'. Did you forget to wrap args in ()?",
                 "Unexpected error message: \"{e}\""
             );
         } else {
@@ -808,7 +1637,7 @@ echo "Should not print $b"
 ```
 "#;
         let err_str = build_command_structure(FILE, true).expect_err("invalid type should be Err");
-        assert_eq!(err_str, expected_err);
+        assert_eq!(err_str.to_string(), expected_err);
         const FILE2: &str = r#"
 ## check
 OPTIONS
@@ -820,7 +1649,172 @@ echo "Should not print $val"
         "#;
         let err_str2 =
             build_command_structure(FILE2, true).expect_err("invalid type should be Err");
-        assert_eq!(err_str2, expected_err);
+        assert_eq!(err_str2.to_string(), expected_err);
+    }
+
+    #[test]
+    fn locates_bad_flag_type() {
+        const FILE: &str =
+            "## check\n\n**OPTIONS**\n\n- flag: -b |strng| A typo\n\n```\necho $b\n```\n";
+        let err = build_command_structure(FILE, true).expect_err("invalid type should be Err");
+        let location = err.location().expect("error should have a location");
+        assert_eq!(location.line, 5);
+        assert_eq!(location.column, 12);
+        assert_eq!(location.len, 7);
+        assert_eq!(location.snippet, "- flag: -b |strng| A typo");
+        assert_eq!(
+            err.with_file("inkjet.md").render(false),
+            "ERROR (inkjet): Invalid flag type 'strng' Expected string | number | bool.
+ --> inkjet.md:5:12
+  |
+5 | - flag: -b |strng| A typo
+  |            ^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn records_script_position() {
+        const FILE: &str =
+            "## first\n\n```\necho first\n```\n\n## second\n\n```bash\necho second\n```\n";
+        let tree = build_command_structure(FILE, true).expect("valid inkfile");
+        let first = &tree.subcommands.first().expect("first command").script;
+        let second = &tree.subcommands.get(1).expect("second command").script;
+        assert_eq!(first.line, 4);
+        assert_eq!(second.line, 10);
+        assert_eq!(
+            FILE.get(second.offset..second.offset + 11),
+            Some("echo second")
+        );
+    }
+
+    #[test]
+    fn parses_completion_scripts() {
+        const FILE: &str = r#"
+## checkout (branch)
+
+**OPTIONS**
+
+- remote
+  - flag: --remote
+  - type: string
+  - complete: git remote
+
+```sh
+git checkout "$branch"
+```
+
+```bash complete branch
+git branch
+```
+"#;
+        let tree = build_command_structure(FILE, true).expect("valid inkfile");
+        let checkout = tree.subcommands.first().expect("checkout command");
+        assert_eq!(checkout.script.source, "git checkout \"$branch\"\n");
+        let branch = checkout.args.first().and_then(|a| a.complete.as_ref());
+        let branch = branch.expect("branch completion");
+        assert_eq!(branch.executor, "bash");
+        assert_eq!(branch.source, "git branch\n");
+        assert_eq!(branch.line, 16);
+        let remote = checkout
+            .named_flags
+            .first()
+            .and_then(|f| f.complete.as_ref());
+        assert_eq!(remote.map(|s| s.source.as_str()), Some("git remote"));
+
+        let err = build_command_structure("## build\n\n```sh complete target\nls\n```\n", true)
+            .expect_err("no arg named target");
+        assert!(
+            matches!(err, ParseError::UnknownCompletionTarget { ref name, .. } if name == "target")
+        );
+    }
+
+    #[test]
+    fn parses_secret_flags() {
+        const FILE: &str = r#"
+## deploy
+
+**OPTIONS**
+
+- token
+  - flag: --token
+  - type: string
+  - secret: true
+- flag: --password |string secret| The admin password
+- flag: --note |string| A secret note for the log
+
+```sh
+deploy
+```
+"#;
+        let tree = build_command_structure(FILE, true).expect("valid inkfile");
+        let deploy = tree.subcommands.first().expect("deploy command");
+        let secrets: Vec<(&str, bool)> = deploy
+            .named_flags
+            .iter()
+            .map(|f| (f.name.as_str(), f.secret))
+            .collect();
+        assert_eq!(
+            secrets,
+            [
+                ("token", true),
+                ("password", true),
+                ("note", false),
+                ("verbose", false)
+            ]
+        );
+        let password = deploy.named_flags.get(1).expect("password flag");
+        assert_eq!(password.desc, "The admin password");
+        let note = deploy.named_flags.get(2).expect("note flag");
+        assert_eq!(note.desc, "A secret note for the log");
+    }
+
+    #[test]
+    fn parses_confirm_options() {
+        const FILE: &str = r#"
+## db
+
+### db drop
+
+**OPTIONS**
+
+- confirm: "This deletes prod data. Continue?"
+- flag: --force |bool| Skip the backup
+
+```sh
+dropdb app
+```
+
+### db reset
+
+**OPTIONS**
+
+- confirm_phrase: prod
+
+```sh
+resetdb app
+```
+"#;
+        let tree = build_command_structure(FILE, true).expect("valid inkfile");
+        let drop = tree.find("db drop").expect("drop command");
+        assert_eq!(drop.confirm, "This deletes prod data. Continue?");
+        assert!(drop.confirm_phrase.is_empty());
+        let flags: Vec<&str> = drop.named_flags.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(flags, ["force", "verbose"]);
+        let reset = tree.find("db reset").expect("reset command");
+        assert_eq!(reset.confirm_phrase, "prod");
+        assert!(reset.confirm.is_empty());
+    }
+
+    #[test]
+    fn locates_errors_in_merged_files() {
+        const FILE: &str = "## main\n\n```\necho main\n```\n\n<!-- inkfile: other/inkjet.md -->\n## my task\n\n```\necho other\n```\n";
+        let err = build_command_structure(FILE, true).expect_err("name has spaces");
+        let err = err.with_file("inkjet.md");
+        let location = err.location().expect("error should have a location");
+        assert_eq!(location.file, "other/inkjet.md");
+        assert_eq!(location.line, 1);
+        assert_eq!(location.column, 4);
+        assert_eq!(location.len, 7);
     }
 
     #[test]
@@ -833,7 +1827,7 @@ echo "Should not print"
 "#;
         let err_str =
             build_command_structure(FILE, true).expect_err("should error on no command name");
-        assert_eq!(err_str, "unexpected empty heading name");
+        assert_eq!(err_str.to_string(), "unexpected empty heading name");
     }
 
     #[test]
@@ -923,3 +1917,112 @@ echo "The flag values are string=$string bool=$bool number=$number"
         assert_eq!(ordered_result, "onetwothree");
     }
 }
+
+#[cfg(test)]
+mod parse_config {
+    use super::*;
+
+    #[test]
+    fn reads_front_matter() {
+        let contents = r#"#!/usr/bin/env inkjet
++++
+sort = true
+fixed_dir = false
+import = ["all"]
++++
+
+## build
+
+```
+echo "build"
+```
+"#;
+        let config = parse_config(contents, false).expect("config should parse");
+        assert!(config.sort);
+        assert!(!config.fixed_dir);
+        assert_eq!(config.import, vec!["all".to_string()]);
+    }
+
+    #[test]
+    fn defaults_without_directives() {
+        let config = parse_config(TEST_INKJETFILE, false).expect("config should parse");
+        assert_eq!(config, InkjetConfig::default());
+        assert!(config.fixed_dir);
+    }
+
+    #[test]
+    fn reads_inline_directives() {
+        let contents = r#"
+inkjet_sort: true
+
+inkjet_fixed_dir: false
+
+## build
+
+```
+echo "build"
+```
+"#;
+        let config = parse_config(contents, false).expect("config should parse");
+        assert!(config.sort);
+        assert!(!config.fixed_dir);
+    }
+
+    #[test]
+    fn ignores_directives_in_code_blocks() {
+        let contents = r#"
+## build
+
+```
+echo "inkjet_sort: true"
+echo "inkjet_import: all"
+```
+"#;
+        let config = parse_config(contents, false).expect("config should parse");
+        assert!(!config.sort);
+        assert!(config.import.is_empty());
+    }
+
+    #[test]
+    fn front_matter_takes_precedence_over_inline_directives() {
+        let contents = r#"+++
+sort = false
++++
+
+inkjet_sort: true
+"#;
+        let config = parse_config(contents, false).expect("config should parse");
+        assert!(!config.sort);
+    }
+
+    #[test]
+    fn keeps_unknown_keys_for_warnings() {
+        let config = parse_config("+++\nsorted = true\n+++\n", false).expect("config should parse");
+        assert!(!config.sort);
+        assert!(config.unknown.contains_key("sorted"));
+    }
+
+    #[test]
+    fn front_matter_is_not_markdown() {
+        let contents = "+++\n# The name of the tool\nname = \"tool\"\nabout = \"\"\"\n## not a command\n\"\"\"\n+++\n\n# Title\n\n## build\n\n```sh\necho build\n```\n";
+        let config = parse_config(contents, false).expect("config should parse");
+        assert_eq!(config.name.as_deref(), Some("tool"));
+        let tree = build_command_structure(contents, false).expect("valid inkfile");
+        assert_eq!(tree.name, "title");
+        let names: Vec<&str> = tree.subcommands.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["build"]);
+        let build = tree.find("build").expect("build command");
+        assert_eq!(contents.get(build.start..build.start + 8), Some("## build"));
+        assert_eq!(blank_front_matter(contents).len(), contents.len());
+    }
+
+    #[test]
+    fn errors_on_invalid_front_matter() {
+        let err = parse_config("+++\nsort = \"yes\"\n+++\n", false)
+            .expect_err("a string is not a boolean");
+        assert!(
+            err.to_string().starts_with("Invalid front matter:"),
+            "{err}"
+        );
+    }
+}
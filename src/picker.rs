@@ -0,0 +1,222 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use console::{Key, Term, truncate_str};
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use std::io::{self, IsTerminal};
+
+use crate::command::CommandBlock;
+
+/// The number of matching commands shown below the search query
+const MAX_SHOWN: usize = 10;
+
+/// Entry is a command that can be picked along with the names that select it from the root.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The names of the command and its parents, i.e. `["services", "start"]`
+    pub path: Vec<String>,
+    /// The first line of the command's description
+    pub summary: String,
+    /// The other names the command can be called by
+    pub aliases: Vec<String>,
+}
+
+impl Entry {
+    /// The text the query is matched against
+    fn haystack(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.path.join(" "),
+            self.aliases.join(" "),
+            self.summary
+        )
+    }
+
+    fn render(&self, selected: bool, color: bool) -> String {
+        let mut line = self.path.join(" ");
+        if !self.aliases.is_empty() {
+            line.push_str(&format!(" ({})", self.aliases.join(", ")));
+        }
+        let summary = if self.summary.is_empty() {
+            "".to_string()
+        } else if color {
+            color_print::cformat!("  <dim>{}</dim>", self.summary)
+        } else {
+            format!("  {}", self.summary)
+        };
+        match (selected, color) {
+            (true, true) => {
+                color_print::cformat!("<cyan><bold>> {}</bold></cyan>{}", line, summary)
+            }
+            (true, false) => format!("> {line}{summary}"),
+            (false, _) => format!("  {line}{summary}"),
+        }
+    }
+}
+
+/// Returns every command that can be run, depth first in document order.
+/// Commands starting with an underscore are hidden from help, so they cannot be picked either.
+pub fn entries(root: &CommandBlock) -> Vec<Entry> {
+    let mut entries = vec![];
+    collect(&root.subcommands, &[], &mut entries);
+    entries
+}
+
+fn collect(subcommands: &[CommandBlock], parent: &[String], entries: &mut Vec<Entry>) {
+    for cmd in subcommands.iter().filter(|c| !c.name.starts_with('_')) {
+        let mut path = parent.to_vec();
+        path.push(cmd.name.clone());
+        if cmd.script.has_script() {
+            entries.push(Entry {
+                path: path.clone(),
+                summary: cmd.desc.lines().next().unwrap_or_default().to_string(),
+                aliases: cmd
+                    .aliases
+                    .split("//")
+                    .filter(|a| !a.is_empty())
+                    .map(|a| a.to_string())
+                    .collect(),
+            });
+        }
+        collect(&cmd.subcommands, &path, entries);
+    }
+}
+
+/// Returns the indexes of the entries that match the query, best match first.
+/// Entries that score the same keep their document order.
+pub fn rank(entries: &[Entry], query: &str) -> Vec<usize> {
+    let matcher = SkimMatcherV2::default();
+    let mut scored: Vec<(i64, usize)> = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            matcher
+                .fuzzy_match(&entry.haystack(), query.trim())
+                .map(|score| (score, i))
+        })
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Lets the user search the entries as they type and returns the path of the picked command.
+/// It returns None if the user cancels with escape or if stdin or stderr is not a terminal.
+pub fn pick(entries: &[Entry], color: bool) -> io::Result<Option<Vec<String>>> {
+    let term = Term::stderr();
+    if !term.is_term() || !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let width = term.size().1 as usize;
+    let mut query = String::new();
+    let mut selected = 0;
+    let mut drawn = 0;
+    loop {
+        let ranked = rank(entries, &query);
+        selected = selected.min(ranked.len().saturating_sub(1));
+        // The list scrolls once the selection moves past the shown commands
+        let offset = selected.saturating_sub(MAX_SHOWN - 1);
+        let mut lines = vec![format!("Pick a command (type to search): {query}")];
+        for (i, &idx) in ranked.iter().enumerate().skip(offset).take(MAX_SHOWN) {
+            if let Some(entry) = entries.get(idx) {
+                lines.push(entry.render(i == selected, color));
+            }
+        }
+        if ranked.is_empty() {
+            lines.push("  No matching commands".to_string());
+        }
+        term.clear_last_lines(drawn)?;
+        for line in &lines {
+            // A wrapped line would throw off the number of lines to clear
+            term.write_line(&truncate_str(line, width, "…"))?;
+        }
+        drawn = lines.len();
+
+        match term.read_key()? {
+            Key::Enter => {
+                if let Some(entry) = ranked.get(selected).and_then(|&idx| entries.get(idx)) {
+                    term.clear_last_lines(drawn)?;
+                    return Ok(Some(entry.path.clone()));
+                }
+            }
+            Key::Escape | Key::CtrlC => {
+                term.clear_last_lines(drawn)?;
+                return Ok(None);
+            }
+            Key::ArrowUp | Key::BackTab => selected = selected.saturating_sub(1),
+            Key::ArrowDown | Key::Tab if selected + 1 < ranked.len() => selected += 1,
+            Key::Backspace => {
+                query.pop();
+                selected = 0;
+            }
+            Key::Char(c) if !c.is_control() => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inkfile::Inkfile;
+
+    const INKFILE: &str = r#"
+## build//b
+
+> Compile the project
+
+```sh
+make
+```
+
+## services
+
+### services start
+
+> Start every service
+
+```sh
+echo start
+```
+
+### services stop
+
+> Stop every service
+
+```sh
+echo stop
+```
+
+## _internal
+
+```sh
+echo hidden
+```
+"#;
+
+    #[test]
+    fn lists_runnable_commands_with_their_paths() {
+        let inkfile = Inkfile::parse(INKFILE).expect("valid inkfile");
+        let paths: Vec<String> = entries(&inkfile.root)
+            .iter()
+            .map(|e| e.path.join(" "))
+            .collect();
+        assert_eq!(paths, ["build", "services start", "services stop"]);
+        let build = entries(&inkfile.root).remove(0);
+        assert_eq!(build.aliases, ["b"]);
+        assert_eq!(build.summary, "Compile the project");
+    }
+
+    #[test]
+    fn ranks_fuzzy_matches() {
+        let inkfile = Inkfile::parse(INKFILE).expect("valid inkfile");
+        let entries = entries(&inkfile.root);
+        assert_eq!(rank(&entries, ""), [0, 1, 2]);
+        assert_eq!(rank(&entries, "sstop"), [2]);
+        assert_eq!(rank(&entries, "compile"), [0]);
+        assert!(rank(&entries, "zzz").is_empty());
+    }
+}
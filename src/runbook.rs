@@ -0,0 +1,395 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use dialoguer::KeyPrompt;
+use dialoguer::theme::ColoredTheme;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use crate::command::CommandBlock;
+use crate::executor::{Outcome, execute_command, hash_source};
+use crate::inkfile::Inkfile;
+use crate::session::Session;
+use crate::{runner, utils, view};
+
+/// State is the progress of a runbook. It is saved after every step so `--resume` can continue after a failure.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    /// The path of the inkfile the runbook was started with
+    inkfile: String,
+    /// The name of the first step that has not completed. It is looked up by name on resume,
+    /// so adding or removing other commands does not move the runbook to a different step.
+    next: String,
+    results: Vec<StepResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StepResult {
+    step: String,
+    outcome: StepOutcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum StepOutcome {
+    Ran,
+    Skipped,
+    Failed(i32),
+}
+
+/// Returns every command that can be run in document order along with its full name, i.e. `services start`.
+/// Commands of imported inkfiles follow those of the root inkfile, in the order the inkfiles were imported.
+/// Commands starting with an underscore are hidden from help, so they are not steps either.
+fn steps(inkfile: &Inkfile) -> Vec<(String, CommandBlock)> {
+    fn collect(
+        subcommands: &[CommandBlock],
+        parent: &str,
+        steps: &mut Vec<(String, CommandBlock)>,
+    ) {
+        for cmd in subcommands.iter().filter(|c| !c.name.starts_with('_')) {
+            let name = format!("{parent} {}", cmd.name).trim().to_string();
+            if cmd.script.has_script() {
+                steps.push((name.clone(), cmd.clone()));
+            }
+            collect(&cmd.subcommands, &name, steps);
+        }
+    }
+    let mut steps = vec![];
+    collect(&inkfile.root.subcommands, "", &mut steps);
+    // Merged and mounted commands are not in the tree in the order they are written
+    let source_index = |cmd: &CommandBlock| {
+        inkfile
+            .sources
+            .iter()
+            .position(|s| !cmd.inkjet_file.is_empty() && s.path == cmd.inkjet_file)
+            .unwrap_or(0)
+    };
+    steps.sort_by_key(|(_, cmd)| (source_index(cmd), cmd.start));
+    steps
+}
+
+/// Returns where the progress of a runbook is saved, i.e. `~/.local/state/inkjet/runbooks/<hash>.json`
+fn state_path(inkfile_path: &str) -> Option<PathBuf> {
+    Some(
+        utils::xdg_dir("XDG_STATE_HOME", ".local/state")?
+            .join("inkjet/runbooks")
+            .join(format!("{}.json", hash_source(inkfile_path))),
+    )
+}
+
+fn load_state(inkfile_path: &str) -> Option<State> {
+    let contents = fs::read(state_path(inkfile_path)?).ok()?;
+    serde_json::from_slice::<State>(&contents)
+        .ok()
+        .filter(|state| state.inkfile == inkfile_path)
+}
+
+fn save_state(state: &State) -> io::Result<()> {
+    let Some(path) = state_path(&state.inkfile) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_vec(state).map_err(io::Error::other)?)
+}
+
+fn clear_state(inkfile_path: &str) {
+    if let Some(path) = state_path(inkfile_path) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Walks every command of the inkfile in document order. Each step's prose is rendered,
+/// then the user picks whether to run, skip, preview or edit it, or to quit.
+/// Progress is saved after each step. If resume is set, the runbook continues from the first step that did not complete.
+/// If session is set, shell blocks run in one long-lived shell. See `Session`.
+/// If save_edits is set, edited scripts are written back into the inkfile.
+/// If yes is set, steps that ask for confirmation run without asking.
+pub fn run(
+    inkfile: &mut Inkfile,
+    resume: bool,
+    session: bool,
+    save_edits: bool,
+    yes: bool,
+    color: bool,
+) -> i32 {
+    // cov:begin-include
+    let mut steps = steps(inkfile);
+    if steps.is_empty() {
+        eprintln!(
+            "{} {} has no commands to run",
+            utils::warning_msg(),
+            inkfile.path
+        );
+        return 0;
+    }
+    let saved = load_state(&inkfile.path).filter(|_| resume);
+    let start = saved
+        .as_ref()
+        .and_then(|state| steps.iter().position(|(name, _)| name == &state.next));
+    let mut state = match (saved, start) {
+        (Some(state), Some(start)) => {
+            eprintln!(
+                "Resuming at step {} of {}: {}",
+                start + 1,
+                steps.len(),
+                state.next
+            );
+            state
+        }
+        (saved, _) => {
+            if let Some(state) = saved {
+                eprintln!(
+                    "{} step {} no longer exists. Starting from the first step.",
+                    utils::info_msg(),
+                    state.next
+                );
+            } else if resume {
+                eprintln!(
+                    "{} no progress saved for {}. Starting from the first step.",
+                    utils::info_msg(),
+                    inkfile.path
+                );
+            }
+            State {
+                inkfile: inkfile.path.clone(),
+                ..State::default()
+            }
+        }
+    };
+    let printer = view::Printer::new(color, &inkfile.path);
+    let fixed_dir = inkfile.config.fixed_dir;
+    let mut session = session.then(Session::new);
+
+    for i in start.unwrap_or(0)..steps.len() {
+        let Some((name, mut step)) = steps.get(i).cloned() else {
+            break;
+        };
+        state.next = name.clone();
+        let heading = format!("Step {} of {}: {name}", i + 1, steps.len());
+        if color {
+            eprintln!(
+                "{}",
+                color_print::cformat!("\n<bold><cyan>{}</cyan></bold>", heading)
+            );
+        } else {
+            eprintln!("\n{heading}");
+        }
+        let portion = inkfile
+            .source_of(&step)
+            .get(step.start..step.end)
+            .unwrap_or_default();
+        if let Err(err) = printer.print_markdown(portion) {
+            eprintln!("{} printing markdown: {err}", utils::error_msg());
+        }
+        eprintln!();
+
+        let mut cmd = step.clone();
+        // Values are prompted for before editing, so they are not prompted for again
+        let mut resolved = false;
+        let outcome = loop {
+            let key = KeyPrompt::with_theme(&ColoredTheme::default())
+                .with_text(&format!(
+                    "Run step {name}? (r)un, (s)kip, (p)review, (e)dit or (q)uit"
+                ))
+                .items(&['r', 's', 'p', 'e', 'q'])
+                .default(0)
+                .interact()
+                .expect("Inkjet: unable to read response");
+            match key {
+                'r' => {
+                    let cmd = if resolved {
+                        cmd.clone()
+                    } else {
+                        runner::prompt_params(cmd.clone(), &BTreeMap::new(), color)
+                    };
+                    if let Some(code) = runner::confirm_command(&cmd, yes, color) {
+                        // A step that is declined is offered again
+                        if io::stdin().is_terminal() {
+                            continue;
+                        }
+                        break StepOutcome::Failed(code);
+                    }
+                    let result = match session.as_mut() {
+                        Some(session) => {
+                            session.execute(cmd, &inkfile.path, false, color, fixed_dir)
+                        }
+                        None => execute_command(cmd, &inkfile.path, false, color, fixed_dir),
+                    };
+                    break match result {
+                        Ok(Outcome::Exited(status)) if status.success() => StepOutcome::Ran,
+                        Ok(Outcome::Exited(status)) => {
+                            StepOutcome::Failed(status.code().unwrap_or(1))
+                        }
+                        Ok(Outcome::Previewed(_)) => StepOutcome::Ran,
+                        Err(err) => {
+                            eprintln!("{err}");
+                            StepOutcome::Failed(5)
+                        }
+                    };
+                }
+                's' => break StepOutcome::Skipped,
+                'p' => {
+                    if let Err(err) =
+                        execute_command(cmd.clone(), &inkfile.path, true, color, fixed_dir)
+                    {
+                        eprintln!("{err}");
+                    }
+                    eprintln!();
+                }
+                'e' => {
+                    if !resolved {
+                        cmd = runner::prompt_params(cmd, &BTreeMap::new(), color);
+                        resolved = true;
+                    }
+                    if !runner::edit_script(&mut cmd) {
+                        continue;
+                    }
+                    if save_edits {
+                        match inkfile.save_script(&step, &cmd.script.source) {
+                            Ok(path) => {
+                                // The steps that follow the edit moved within the file
+                                steps = self::steps(inkfile);
+                                if let Some((_, saved)) = steps.get(i) {
+                                    step = saved.clone();
+                                }
+                                eprintln!("Saved edits to {path}")
+                            }
+                            Err(err) => {
+                                eprintln!("{} unable to save edits: {err}", utils::error_msg())
+                            }
+                        }
+                    } else {
+                        eprintln!("Edited step {name}. The edit applies to this run only.");
+                    }
+                }
+                _ => {
+                    save(&state);
+                    print_summary(&steps, &state, color);
+                    eprintln!(
+                        "Stopped before step {}. Run again with --runbook --resume to continue.",
+                        i + 1
+                    );
+                    return 0;
+                }
+            }
+        };
+
+        // A step that is retried after a failure replaces its earlier result
+        state.results.retain(|r| r.step != name);
+        state.results.push(StepResult {
+            step: name.clone(),
+            outcome,
+        });
+        if let StepOutcome::Failed(code) = outcome {
+            save(&state);
+            print_summary(&steps, &state, color);
+            eprintln!("Step {name} failed. Run again with --runbook --resume to retry it.");
+            return code;
+        }
+        state.next = steps
+            .get(i + 1)
+            .map(|(next, _)| next.clone())
+            .unwrap_or_default();
+        save(&state);
+    }
+    print_summary(&steps, &state, color);
+    clear_state(&inkfile.path);
+    0
+    // cov:end-include
+}
+
+fn save(state: &State) {
+    if let Err(err) = save_state(state) {
+        eprintln!(
+            "{} unable to save runbook progress: {err}",
+            utils::warning_msg()
+        );
+    }
+}
+
+fn print_summary(steps: &[(String, CommandBlock)], state: &State, color: bool) {
+    let width = steps
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default();
+    let (mut ran, mut skipped, mut failed) = (0, 0, 0);
+    eprintln!("\nRunbook summary:");
+    for (name, _) in steps {
+        let outcome = state
+            .results
+            .iter()
+            .find(|r| &r.step == name)
+            .map(|r| r.outcome);
+        let text = match outcome {
+            Some(StepOutcome::Ran) => {
+                ran += 1;
+                "ran".to_string()
+            }
+            Some(StepOutcome::Skipped) => {
+                skipped += 1;
+                "skipped".to_string()
+            }
+            Some(StepOutcome::Failed(code)) => {
+                failed += 1;
+                format!("failed (exit {code})")
+            }
+            None => "not run".to_string(),
+        };
+        let text = match (color, outcome) {
+            (true, Some(StepOutcome::Ran)) => color_print::cformat!("<green>{}</green>", text),
+            (true, Some(StepOutcome::Failed(_))) => color_print::cformat!("<red>{}</red>", text),
+            _ => text,
+        };
+        eprintln!("  {name:width$}  {text}");
+    }
+    eprintln!("{ran} ran, {skipped} skipped, {failed} failed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_steps_in_document_order() {
+        let inkfile = Inkfile::parse(
+            r#"
+# Guide
+
+## install
+
+```sh
+echo install
+```
+
+## services
+
+### services start
+
+```sh
+echo start
+```
+
+## _cleanup
+
+```sh
+echo hidden
+```
+
+## verify
+
+```sh
+echo verify
+```
+"#,
+        )
+        .expect("valid inkfile");
+        let names: Vec<String> = steps(&inkfile).into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, ["install", "services start", "verify"]);
+    }
+}
@@ -3,26 +3,45 @@
 
 use clap::error::ErrorKind;
 use dialoguer::theme::ColoredTheme;
-use dialoguer::{Confirmation, Input, KeyPrompt};
-use std::collections::HashSet;
+use dialoguer::{Checkboxes, Confirmation, Editor, Input, KeyPrompt, PasswordInput, Select};
+use std::collections::{BTreeMap, HashSet};
 use std::env;
+use std::ffi::OsStr;
+use std::io::IsTerminal;
 use std::path::Path;
 
-use clap::{Arg, ArgMatches, ColorChoice, Command, builder::styling};
-use clap_complete::{Shell, generate};
-
-use crate::command::CommandBlock;
-use crate::executor::{execute_command, execute_merge_command};
+use clap::builder::{PossibleValue, StringValueParser, TypedValueParser, styling};
+use clap::{Arg, ArgMatches, ColorChoice, Command};
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate, ValueCompleter};
+use clap_complete::{CompleteEnv, Shell, generate};
+use clap_complete_nushell::Nushell;
+
+use crate::answers::Answers;
+use crate::command::{CommandBlock, Script};
+use crate::executor::{Outcome, completion_candidates, execute_command};
+use crate::inkfile::Inkfile;
+use crate::session::Session;
 use crate::{utils, view};
 
+/// The environment variable that requests completions from clap's dynamic engine, i.e. `INKJET_COMPLETE=bash`.
+const COMPLETE_VAR: &str = "INKJET_COMPLETE";
+
+/// The environment variable that runs commands that ask for confirmation without asking, i.e. `INKJET_YES=1`.
+const YES_VAR: &str = "INKJET_YES";
+
+/// The exit code when a command that asks for confirmation is run without a terminal. It is EX_NOPERM of sysexits.h.
+const NOT_CONFIRMED_CODE: i32 = 77;
+
 /// Parse and execute the chosen command.
 /// run attempts to ensure that the process does not exit unless there is a panic or clap --help or --version is matched.
 /// This enables improved integration testing.
 /// Returns exit code, an error string if it should be printed, and if the error should be prefixed with `ERROR`.
 /// Inkjet parser created by Brandon Kalinowski See: https://github.com/brandonkal/inkjet
 pub fn run(args: Vec<String>, color: bool) -> i32 {
+    // Completions from clap's dynamic engine are requested with INKJET_COMPLETE=<shell>
+    let completing = env::var(COMPLETE_VAR).is_ok_and(|v| !v.is_empty() && v != "0");
     let early_version_detected = match args.get(1) {
-        Some(first_arg) => first_arg == "-V" || first_arg == "--version",
+        Some(first_arg) => !completing && (first_arg == "-V" || first_arg == "--version"),
         _ => false,
     };
 
@@ -47,7 +66,7 @@ pub fn run(args: Vec<String>, color: bool) -> i32 {
         .trailing_var_arg(true)
         .version(env!("CARGO_PKG_VERSION"))
         .about("Inkjet parser created by Brandon Kalinowski\nInkjet is a tool to build interactive CLIs with executable markdown documents.\nSee: https://github.com/brandonkal/inkjet")
-        .after_help("Run 'inkjet --inkjet-print-all' if you wish to view the complete merged inkjet definition.\nRun 'inkjet --inkjet-dynamic-completions fish/bash/zsh/powershell' to generate shell completions.\nThis is called dynamically by the global shell completion scripts.\nRun 'inkjet COMMAND --help' for more information on a command.")
+        .after_help("Run 'inkjet --inkjet-print-all' if you wish to view the complete merged inkjet definition.\nRun 'inkjet --inkjet-print-imports' to list the imported inkfiles.\nRun 'inkjet --inkjet-check [--scripts] [--format json]' to check the inkfile for problems.\nRun 'inkjet --inkjet-dump [json|yaml]' to print the parsed command tree.\nRun 'inkjet --inkjet-bundle -o OUTPUT [INKFILE]' to build a standalone executable for the inkfile.\nRun 'inkjet --inkjet-man [-o DIR]' to print a man page, or write one for each command into DIR.\nRun 'inkjet --inkjet-docs [markdown|html]' to print a reference of every command.\nRun 'inkjet --inkjet-install-completions bash/zsh/fish/elvish/nushell' to install shell completions.\nRun 'inkjet inkjet-dynamic-completions bash/zsh/fish/elvish/nushell/powershell' to print a static completion script.\nRun 'inkjet COMMAND --help' for more information on a command.")
         .arg(custom_inkfile_path_arg())
         .arg(
             Arg::new("interactive")
@@ -62,128 +81,399 @@ pub fn run(args: Vec<String>, color: bool) -> i32 {
                 .long("preview")
                 .help("Preview the command source and exit")
                 .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("session")
+                .long("session")
+                .help("Run the shell blocks of interactive and runbook steps in one long-lived shell")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("save-edits")
+                .long("save-edits")
+                .help("Write scripts edited in interactive and runbook steps back into the inkfile")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-remember")
+                .long("no-remember")
+                .help("Do not fill interactive prompts with the values given last time, nor remember this run's values")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("forget")
+                .long("forget")
+                .help("Clear the interactive answers remembered for the inkfile and exit")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("yes")
+                .long("yes")
+                .help("Run commands that ask for confirmation without asking. Set INKJET_YES=1 to do the same")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("runbook")
+                .long("runbook")
+                .help("Step through every command in document order. Add --resume to continue after a failure")
+                .action(clap::ArgAction::SetTrue),
         );
 
+    // An inkfile bundled into this executable is used unless another inkfile is given
+    let embedded = crate::loader::bundled();
+
     // Handle version command early here
     if early_version_detected {
-        println!("inkjet {}", env!("CARGO_PKG_VERSION"));
+        match &embedded {
+            Some(bundle) => println!(
+                "{} {}",
+                program_name(bundle, true).unwrap_or_default(),
+                program_version(bundle)
+            ),
+            None => println!("inkjet {}", env!("CARGO_PKG_VERSION")),
+        }
         return 0;
     }
-    let (opts, args) = pre_parse(args);
+    let raw_args = args.clone();
+    let (mut opts, mut args) = if completing {
+        // Only the inkfile is taken from the words being completed
+        let (opts, args) = pre_parse(completion_inkfile_args(&raw_args));
+        let opts = CustomOpts {
+            inkfile_opt: opts.inkfile_opt,
+            ..CustomOpts::default()
+        };
+        (opts, args)
+    } else {
+        pre_parse(args)
+    };
+    // The completions mode may follow --inkfile, i.e. for an alias of `inkjet --inkfile ~/deploy.md`
+    let completions_shell = args
+        .iter()
+        .position(|a| a == "inkjet-dynamic-completions")
+        .and_then(|i| args.get(i + 1))
+        .filter(|_| !completing)
+        .cloned();
+    let in_completions_mode = completions_shell.is_some();
+
+    let embedded = embedded.filter(|_| opts.inkfile_opt.is_empty());
+    let bundled = embedded.is_some();
+    if opts.install_completions {
+        // The wrappers of a bundled CLI call the bundled executable
+        let program = embedded
+            .as_ref()
+            .and_then(|bundle| program_name(bundle, true))
+            .unwrap_or_else(|| "inkjet".to_string());
+        return match crate::completions::install(&opts.install_shell, &program) {
+            Ok((path, hint)) => {
+                eprintln!(
+                    "Installed {} completions for {program} to {}",
+                    opts.install_shell,
+                    path.display()
+                );
+                if let Some(hint) = hint {
+                    eprintln!("{hint}");
+                }
+                0
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::InvalidInput => {
+                let err = cli_app.error(ErrorKind::InvalidValue, err.to_string());
+                let _ = err.print();
+                err.exit_code()
+            }
+            Err(err) => {
+                eprintln!("{} {err}", utils::error_msg());
+                5
+            }
+        };
+    }
+    let mut inkfile = match embedded {
+        Some(inkfile) if opts.check => {
+            let diagnostics = crate::lint::check_inkfile(&inkfile, opts.check_scripts);
+            return report_diagnostics(&diagnostics, &inkfile.path, &opts, color, &mut cli_app);
+        }
+        Some(inkfile) => inkfile,
+        None => {
+            let (inkfile, inkfile_path) = crate::loader::find_inkfile(&opts.inkfile_opt);
+            if inkfile.is_err() {
+                if completing {
+                    // Without an inkfile only inkjet's own flags can be completed
+                    return complete_dynamic(cli_app, raw_args);
+                }
+                if opts.inkfile_opt.is_empty() || opts.inkfile_opt == "./inkjet.md" {
+                    // Just log a warning and let the process continue
+                    // we use an if statement here because clap is not printing this as we want
+                    // it to be printed even if a valid match is found (such as help or version)
+                    if color {
+                        eprintln!("{} no inkjet.md found", utils::warning_msg());
+                    } else {
+                        eprintln!("{} no inkjet.md found", utils::plain_msg("WARNING"));
+                    }
 
-    let (inkfile, inkfile_path) = crate::loader::find_inkfile(&opts.inkfile_opt);
-    if inkfile.is_err() {
-        if opts.inkfile_opt.is_empty() || opts.inkfile_opt == "./inkjet.md" {
-            // Just log a warning and let the process continue
-            // we use an if statement here because clap is not printing this as we want
-            // it to be printed even if a valid match is found (such as help or version)
-            if color {
-                eprintln!("{} no inkjet.md found", utils::WARNING_MSG);
+                    // If the inkfile can't be found, at least parse for --version or --help
+                    if let Err(err) = cli_app.clone().try_get_matches_from(args) {
+                        // Parsed as version or help most likely
+                        // if --help is called, clap still considers it an error.
+                        let _ = err.print();
+                        return err.exit_code();
+                    };
+                    return 66; // cov:ignore (won't be called if help is parsed)
+                } else {
+                    let red_inkjet: &'static str =
+                        color_print::cstr!("<bold><underline><red>INKJET</red></underline></bold>");
+                    let err = cli_app.error(
+                        ErrorKind::ValueValidation,
+                        format!(
+                            "{} specified inkfile \"{}\" not found",
+                            red_inkjet, opts.inkfile_opt
+                        ),
+                    );
+                    let _ = err.print();
+                    return 66; // won't be called if help is parsed
+                }
+            }
+            let mdtxt = inkfile.unwrap();
+
+            if opts.check {
+                let diagnostics = crate::lint::check(&mdtxt, &inkfile_path, opts.check_scripts);
+                return report_diagnostics(&diagnostics, &inkfile_path, &opts, color, &mut cli_app);
+            }
+
+            // Completions and help run often and only need the command tree, so they use the cache
+            let wants_help = args.iter().any(|a| a == "-h" || a == "--help");
+            let cacheable =
+                (completing || in_completions_mode || wants_help) && crate::cache::enabled();
+            if let Some(inkfile) = cacheable
+                .then(|| crate::cache::load(&inkfile_path))
+                .flatten()
+            {
+                inkfile
             } else {
-                eprintln!("WARNING (inkjet): no inkjet.md found");
+                // Each imported inkfile is parsed on its own and mounted into the root command tree
+                match Inkfile::parse_with(
+                    &mdtxt,
+                    &inkfile_path,
+                    !in_completions_mode && !completing,
+                ) {
+                    Ok(inkfile) => {
+                        if cacheable {
+                            // A cache that cannot be written only costs speed
+                            let _ = crate::cache::store(&inkfile);
+                        }
+                        inkfile
+                    }
+                    Err(err) => {
+                        eprintln!("{}", err.with_file(&inkfile_path).render(color));
+                        return 78;
+                    }
+                }
             }
+        }
+    };
+    let inkfile_path = inkfile.path.clone();
+    let config = &inkfile.config;
 
-            // If the inkfile can't be found, at least parse for --version or --help
-            if let Err(err) = cli_app.clone().try_get_matches_from(args) {
-                // Parsed as version or help most likely
-                // if --help is called, clap still considers it an error.
-                let _ = err.print();
-                return err.exit_code();
-            };
-            return 66; // cov:ignore (won't be called if help is parsed)
-        } else {
-            let red_inkjet: &'static str =
-                color_print::cstr!("<bold><underline><red>INKJET</red></underline></bold>");
+    if opts.bundle {
+        if opts.output.is_empty() {
             let err = cli_app.error(
-                ErrorKind::ValueValidation,
-                format!(
-                    "{} specified inkfile \"{}\" not found",
-                    red_inkjet, opts.inkfile_opt
-                ),
+                ErrorKind::MissingRequiredArgument,
+                "--inkjet-bundle requires an output path: inkjet --inkjet-bundle -o <OUTPUT> [INKFILE]",
             );
             let _ = err.print();
-            return 66; // won't be called if help is parsed
+            return err.exit_code();
         }
-    }
-    let mut mdtxt = inkfile.unwrap();
-
-    // If import directive is included,
-    // merge all files first and then parse resulting text output
-    if mdtxt.contains("inkjet_import: all") {
-        match execute_merge_command(&inkfile_path) {
-            Ok(txt) => {
-                mdtxt = txt;
+        return match crate::bundle::write(&inkfile, Path::new(&opts.output)) {
+            Ok(()) => {
+                eprintln!("Bundled {inkfile_path} into {}", opts.output);
+                0
             }
-
-            Err(err_string) /* cov:include */ => {
-                let err = cli_app.error(ErrorKind::Io, format!("{} {}", utils::INVALID_MSG, err_string));
-                let _ = err.print();
-                return 5;
+            Err(err) => {
+                eprintln!(
+                    "{} unable to write {}: {err}",
+                    utils::error_msg(),
+                    opts.output
+                );
+                5
             }
         };
     }
-    if opts.print_all {
-        println!("{mdtxt}");
-        return 0;
-    }
+
     // By default subcommands in the help output are listed in the same order
     // they are defined in the markdown file. Users can define this directive
     // for alphabetical sort.
-    let alphabetical_sort = mdtxt.contains("inkjet_sort: true");
-
-    let in_completions_mode =
-        args.len() > 2 && args.get(1).unwrap_or(&String::from("")) == "inkjet-dynamic-completions";
-    let root_command = match crate::parser::build_command_structure(&mdtxt, !in_completions_mode) {
-        Ok(cmd) => cmd,
-        Err(err_string) => {
-            let prefix = if color {
-                utils::ERROR_MSG
-            } else {
-                "ERROR (inkjet):"
-            };
-            eprintln!("{prefix} {err_string}");
-            return 78;
+    let alphabetical_sort = config.sort;
+    if opts.print_all {
+        println!("{}", inkfile.merged_text());
+        return 0;
+    }
+    if opts.print_imports {
+        for source in inkfile.sources.iter().skip(1) {
+            println!("{}", source.path);
         }
+        return 0;
+    }
+    if opts.dump {
+        let dump = inkfile.dump();
+        let serialized = match opts.dump_format.as_str() {
+            "json" => serde_json::to_string_pretty(&dump).map_err(|e| e.to_string()),
+            "yaml" => serde_yaml::to_string(&dump).map_err(|e| e.to_string()),
+            format => {
+                let err = cli_app.error(
+                    ErrorKind::InvalidValue,
+                    format!("Unsupported format: {format}. Expected json or yaml."),
+                );
+                let _ = err.print();
+                return err.exit_code();
+            }
+        };
+        match serialized {
+            Ok(text) => println!("{}", text.trim_end()),
+            Err(err) => {
+                eprintln!("{} {err}", utils::error_msg()); // cov:include (unusual error)
+                return 5;
+            }
+        }
+        return 0;
+    }
+    let root_command = &inkfile.root;
+    let program = program_name(&inkfile, bundled);
+    if let Some(name) = &program {
+        utils::set_program_name(name);
+    }
+    if opts.forget {
+        return match crate::answers::forget(&inkfile.path) {
+            Ok(true) => {
+                eprintln!("Forgot the answers remembered for {}", inkfile.path);
+                0
+            }
+            Ok(false) => {
+                eprintln!("No answers are remembered for {}", inkfile.path);
+                0
+            }
+            Err(err) => {
+                eprintln!("{} unable to forget answers: {err}", utils::error_msg());
+                5
+            }
+        };
+    }
+    if opts.runbook {
+        let session = opts.session || config.session;
+        return crate::runbook::run(
+            &mut inkfile,
+            opts.resume,
+            session,
+            opts.save_edits,
+            opts.yes,
+            color,
+        );
+    }
+    let about_txt = match (&config.about, &program) {
+        (Some(about), _) => about.clone(),
+        // A CLI with its own name is its own tool, so it does not advertise inkjet
+        (None, Some(_)) => root_command.desc.clone(),
+        (None, None) => format!(
+            "Generated from {}\n\nInkjet parser created by Brandon Kalinowski\nInkjet is a tool to build interactive CLIs with executable markdown documents.\nSee: https://github.com/brandonkal/inkjet\n\n{}",
+            inkfile_path, root_command.desc
+        ),
     };
-    let about_txt = format!(
-        "Generated from {}\n\nInkjet parser created by Brandon Kalinowski\nInkjet is a tool to build interactive CLIs with executable markdown documents.\nSee: https://github.com/brandonkal/inkjet\n\n{}",
-        inkfile_path, root_command.desc
-    );
-    cli_app = cli_app.about(about_txt.trim().to_string());
+    cli_app = cli_app
+        .about(about_txt.trim().to_string())
+        .version(program_version(&inkfile));
+    if let Some(name) = &program {
+        cli_app = cli_app
+            .name(name.clone())
+            .bin_name(name.clone())
+            .after_help(None::<&str>);
+    }
+    if let Some(author) = &config.author {
+        cli_app = cli_app.author(author.clone()).help_template(
+            "{before-help}{about-with-newline}{author-with-newline}\n{usage-heading} {usage}\n\n{all-args}{after-help}",
+        );
+    }
+    // With no command and no default command, let the user search for one
+    if opts.no_command
+        && (opts.interactive || config.picker)
+        && !completing
+        && !in_completions_mode
+        && !has_default_command(root_command)
+    {
+        match crate::picker::pick(&crate::picker::entries(root_command), color) {
+            Ok(Some(path)) => {
+                args.pop(); // the implicit default
+                args.extend(path);
+                opts.interactive = true;
+            }
+            Ok(None) if std::io::stdin().is_terminal() => {
+                eprintln!("No command picked");
+                return 0;
+            }
+            // Without a terminal clap reports the missing command as before
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("{} unable to pick a command: {err}", utils::error_msg()); // cov:include (unusual error)
+                return 5;
+            }
+        }
+    }
     cli_app = build_subcommands(
         cli_app,
         &opts,
+        &inkfile,
         root_command.subcommands.clone(),
         alphabetical_sort,
     );
 
+    if completing {
+        return complete_dynamic(cli_app, raw_args);
+    }
+    if opts.man {
+        return write_man_pages(cli_app, &opts.output);
+    }
+    if opts.docs {
+        let program = utils::program_name();
+        match opts.docs_format.as_str() {
+            "markdown" | "md" => print!("{}", crate::docs::markdown(&inkfile, &program)),
+            "html" => print!("{}", crate::docs::html(&inkfile, &program)),
+            format => {
+                let err = cli_app.error(
+                    ErrorKind::InvalidValue,
+                    format!("Unsupported format: {format}. Expected markdown or html."),
+                );
+                let _ = err.print();
+                return err.exit_code();
+            }
+        }
+        return 0;
+    }
+
     // Manual arg parsing for inkjet-dynamic-completions because it should not be required
-    #[allow(clippy::indexing_slicing)]
-    if in_completions_mode {
-        let shell = match args[2].as_str() {
-            "bash" => Shell::Bash,
-            "fish" => Shell::Fish,
-            "zsh" => Shell::Zsh,
-            "powershell" => Shell::PowerShell,
+    if let Some(shell_name) = completions_shell {
+        let mut buffer: Vec<u8> = Vec::new();
+        let bin_name = utils::program_name();
+        match shell_name.as_str() {
+            "bash" => generate(Shell::Bash, &mut cli_app, bin_name, &mut buffer),
+            "fish" => generate(Shell::Fish, &mut cli_app, bin_name, &mut buffer),
+            "zsh" => generate(Shell::Zsh, &mut cli_app, bin_name, &mut buffer),
+            "powershell" => generate(Shell::PowerShell, &mut cli_app, bin_name, &mut buffer),
+            "elvish" => generate(Shell::Elvish, &mut cli_app, bin_name, &mut buffer),
+            "nushell" | "nu" => generate(Nushell, &mut cli_app, bin_name, &mut buffer),
             _ => {
                 let err = cli_app.error(
                     ErrorKind::ValueValidation,
-                    format!("Unsupported shell: {}", args[2]),
+                    format!("Unsupported shell: {shell_name}"),
                 );
                 let _ = err.print();
                 return err.exit_code();
             }
         };
-        let mut buffer: Vec<u8> = Vec::new();
-        generate(shell, &mut cli_app, "inkjet", &mut buffer);
         let mut output = String::from_utf8_lossy(&buffer).into_owned();
-        if args[2].as_str() == "bash" {
+        if shell_name == "bash" {
             output = output
                 .lines()
                 .filter(|line| !line.contains("complete"))
                 .collect::<Vec<&str>>()
                 .join("\n")
-        } else if args[2].as_str() == "fish" {
+        } else if shell_name == "fish" {
             // There is a bug in clap where it adds help commands to completions.
             // So we filter it out here.
             output = output
@@ -203,6 +493,8 @@ pub fn run(args: Vec<String>, color: bool) -> i32 {
             return err.exit_code();
         }
     };
+    // --yes is global, so it may follow the command name where the modifiers are not scanned
+    opts.yes |= matches.get_flag("yes");
 
     let mut chosen_cmd = find_command(&matches, &root_command.subcommands)
         .expect("Inkjet: SubcommandRequired failed to work");
@@ -211,87 +503,469 @@ pub fn run(args: Vec<String>, color: bool) -> i32 {
         let _ = err.print();
         return err.exit_code();
     }
-    let fixed_pwd = !mdtxt.contains("inkjet_fixed_dir: false");
+    let fixed_pwd = config.fixed_dir;
 
     if opts.interactive {
-        let p = view::Printer::new(color, &inkfile_path);
+        match interactive_step(
+            chosen_cmd,
+            &command_path(&matches).join(" "),
+            &mut inkfile,
+            &opts,
+            color,
+            &mut cli_app,
+        ) {
+            Ok(cmd) => chosen_cmd = cmd,
+            Err(code) => return code,
+        }
+    }
+    if !opts.preview
+        && let Some(code) = confirm_command(&chosen_cmd, opts.yes, color)
+    {
+        return code;
+    }
+    if opts.interactive && !opts.preview && (opts.session || inkfile.config.session) {
+        return run_session(chosen_cmd, &mut inkfile, &opts, color, &mut cli_app);
+    }
+    let result = execute_command(chosen_cmd, &inkfile_path, opts.preview, color, fixed_pwd);
+    match result {
+        Ok(Outcome::Exited(status)) => status.code().unwrap_or_default(), // default is 0 status code (success)
+        Ok(Outcome::Previewed(_)) => 0,
+        Err(err_original) => {
+            eprintln!("{err_original}");
+            5
+        }
+    }
+}
 
-        let portion = &mdtxt
-            .get(chosen_cmd.start..chosen_cmd.end)
-            .expect("Inkjet: portion out of bounds");
-        let print_result = p.print_markdown(portion);
-        if let Err(err_box) = print_result {
-            let err = cli_app.error(
-                ErrorKind::Io,
-                format!("{} printing markdown: {}", utils::ERROR_MSG, err_box),
-            );
-            let _ = err.print();
-            return 5; // cov:include (unusual error)
-        }
-        eprintln!();
-        let (picked_cmd, exit_code, err_str) =
-            interactive_params(chosen_cmd, &inkfile_path, color, fixed_pwd);
-        if picked_cmd.is_none() {
-            eprintln!("{err_str}");
-            return exit_code; // cov:include (skipped command)
-        }
-        chosen_cmd = picked_cmd.unwrap();
-    }
-    match execute_command(chosen_cmd, &inkfile_path, opts.preview, color, fixed_pwd) {
-        Some(result) => match result {
-            Ok(status) => status.code().unwrap_or_default(), // default is 0 status code (success)
-            Err(err_original) => {
-                eprintln!("{err_original}");
+/// Renders the markdown of a command, asks whether to run it and prompts for its values.
+/// The values are remembered as the next defaults unless `--no-remember` is given.
+/// If the command should not run, the exit code to stop with is returned instead.
+fn interactive_step(
+    chosen_cmd: CommandBlock,
+    command_path: &str,
+    inkfile: &mut Inkfile,
+    opts: &CustomOpts,
+    color: bool,
+    cli_app: &mut Command,
+) -> Result<CommandBlock, i32> {
+    let inkfile_path = inkfile.path.clone();
+    let p = view::Printer::new(color, &inkfile_path);
+
+    let portion = inkfile
+        .source_of(&chosen_cmd)
+        .get(chosen_cmd.start..chosen_cmd.end)
+        .expect("Inkjet: portion out of bounds");
+    let print_result = p.print_markdown(portion);
+    if let Err(err_box) = print_result {
+        let err = cli_app.error(
+            ErrorKind::Io,
+            format!("{} printing markdown: {}", utils::error_msg(), err_box),
+        );
+        let _ = err.print();
+        return Err(5); // cov:include (unusual error)
+    }
+    eprintln!();
+    // The last values given for this command become the defaults of its prompts
+    let remember = !opts.no_remember;
+    let mut answers = remember.then(|| Answers::load(&inkfile_path));
+    let remembered = answers
+        .as_ref()
+        .map(|a| a.of(command_path))
+        .unwrap_or_default();
+    let (picked_cmd, exit_code, err_str) = interactive_params(
+        chosen_cmd,
+        command_path,
+        &remembered,
+        inkfile,
+        color,
+        opts.save_edits,
+    );
+    let Some(chosen_cmd) = picked_cmd else {
+        eprintln!("{err_str}");
+        return Err(exit_code); // cov:include (skipped command)
+    };
+    if let Some(answers) = answers.as_mut() {
+        answers.record(command_path, &chosen_cmd);
+        if let Err(err) = answers.save() {
+            eprintln!("{} unable to remember answers: {err}", utils::warning_msg());
+        }
+    }
+    Ok(chosen_cmd)
+}
+
+/// Runs interactive steps in one session, so each step sees the directory and variables the previous ones left.
+/// After each step the picker offers the next command. The session ends when the picker is cancelled.
+fn run_session(
+    first: CommandBlock,
+    inkfile: &mut Inkfile,
+    opts: &CustomOpts,
+    color: bool,
+    cli_app: &mut Command,
+) -> i32 {
+    // cov:begin-include
+    let mut session = Session::new();
+    let mut chosen_cmd = first;
+    loop {
+        let result = session.execute(
+            chosen_cmd,
+            &inkfile.path,
+            false,
+            color,
+            inkfile.config.fixed_dir,
+        );
+        let code = match result {
+            Ok(Outcome::Exited(status)) => status.code().unwrap_or_default(),
+            Ok(Outcome::Previewed(_)) => 0,
+            Err(err) => {
+                eprintln!("{err}");
                 5
             }
+        };
+        chosen_cmd = loop {
+            eprintln!("\nPick the next step of the session, or press escape to end it.");
+            let path = match crate::picker::pick(&crate::picker::entries(&inkfile.root), color) {
+                Ok(Some(path)) => path.join(" "),
+                Ok(None) => return code,
+                Err(err) => {
+                    eprintln!("{} unable to pick a command: {err}", utils::error_msg());
+                    return code;
+                }
+            };
+            let Some(next) = inkfile.find(&path).cloned() else {
+                return code;
+            };
+            if let Ok(cmd) = interactive_step(next, &path, inkfile, opts, color, cli_app)
+                && confirm_command(&cmd, opts.yes, color).is_none()
+            {
+                break cmd;
+            }
+        };
+    }
+    // cov:end-include
+}
+
+/// Prints the registration script or the candidates of a completion request from clap's dynamic engine.
+fn complete_dynamic(cli_app: Command, args: Vec<String>) -> i32 {
+    let current_dir = env::current_dir().ok();
+    match CompleteEnv::with_factory(|| cli_app.clone())
+        .var(COMPLETE_VAR)
+        .try_complete(args, current_dir.as_deref())
+    {
+        Ok(_) => 0,
+        Err(err) => {
+            let _ = err.print();
+            err.exit_code()
+        }
+    }
+}
+
+/// Returns the args that select the inkfile of a completion request.
+/// The words being completed follow `--`, but a wrapper script may pass --inkfile before it.
+fn completion_inkfile_args(args: &[String]) -> Vec<String> {
+    match args.iter().position(|a| a == "--") {
+        // The first word after `--` is the program being completed
+        Some(i) => args
+            .iter()
+            .take(i)
+            .chain(args.iter().skip(i + 2))
+            .cloned()
+            .collect(),
+        None => args.to_vec(),
+    }
+}
+
+/// Renders the man page for the CLI to stdout. If a directory is given, a page is written for each command instead.
+fn write_man_pages(cli_app: Command, dir: &str) -> i32 {
+    // The hints about inkjet's own modifiers do not belong in the manual of an inkfile
+    let cli_app = cli_app.after_help(None::<&str>);
+    let result = if dir.is_empty() {
+        clap_mangen::Man::new(cli_app).render(&mut std::io::stdout())
+    } else {
+        std::fs::create_dir_all(dir).and_then(|_| clap_mangen::generate_to(cli_app, dir))
+    };
+    match result {
+        Ok(()) => {
+            if !dir.is_empty() {
+                eprintln!("Wrote man pages to {dir}");
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("{} unable to write man pages: {err}", utils::error_msg());
+            5
+        }
+    }
+}
+
+/// Prints the diagnostics of `--inkjet-check` in the requested format and returns the exit code
+fn report_diagnostics(
+    diagnostics: &[crate::lint::Diagnostic],
+    inkfile_path: &str,
+    opts: &CustomOpts,
+    color: bool,
+    cli_app: &mut Command,
+) -> i32 {
+    match opts.check_format.as_str() {
+        "text" => {
+            for diagnostic in diagnostics {
+                println!("{}", diagnostic.render(color));
+            }
+            if diagnostics.is_empty() {
+                println!("No problems found in {inkfile_path}");
+            }
+        }
+        "json" => match serde_json::to_string_pretty(diagnostics) {
+            Ok(json) => println!("{json}"),
+            Err(err) => {
+                eprintln!("{} {err}", utils::error_msg()); // cov:include (unusual error)
+                return 5;
+            }
         },
-        _ => 0,
+        format => {
+            let err = cli_app.error(
+                ErrorKind::InvalidValue,
+                format!("Unsupported format: {format}. Expected text or json."),
+            );
+            let _ = err.print();
+            return err.exit_code();
+        }
+    }
+    if crate::lint::has_errors(diagnostics) {
+        78
+    } else {
+        0
     }
 }
 
+/// The program name from the `name` directive. A bundled CLI defaults to the name of its executable.
+/// It is None if the inkfile should be presented as inkjet.
+fn program_name(inkfile: &Inkfile, bundled: bool) -> Option<String> {
+    inkfile.config.name.clone().or_else(|| {
+        Path::new(&inkfile.path)
+            .file_stem()
+            .filter(|_| bundled)
+            .map(|s| s.to_string_lossy().to_string())
+    })
+}
+
+/// Returns true if a top level command is named or aliased `default`
+fn has_default_command(root: &CommandBlock) -> bool {
+    root.subcommands
+        .iter()
+        .any(|c| c.name == "default" || c.aliases.split("//").any(|a| a == "default"))
+}
+
+/// The version from the `version` directive. It defaults to the version of inkjet.
+fn program_version(inkfile: &Inkfile) -> String {
+    inkfile
+        .config
+        .version
+        .clone()
+        .unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string())
+}
+
 /// Prompt for missing parameters interactively.
+/// The script can be edited before it runs. The edit applies to this run only unless save_edits is set.
+/// command_path finds the command again once a saved edit has moved it.
 fn interactive_params(
-    mut chosen_cmd: CommandBlock,
-    inkfile_path: &str,
+    chosen_cmd: CommandBlock,
+    command_path: &str,
+    remembered: &BTreeMap<String, String>,
+    inkfile: &mut Inkfile,
     color: bool,
-    fixed_dir: bool,
+    save_edits: bool,
 ) -> (Option<CommandBlock>, i32, String) {
     // cov:begin-include
+    let mut original = chosen_cmd.clone();
+    let mut chosen_cmd = chosen_cmd;
+    // Values are prompted for before editing, so they are not prompted for again
+    let mut resolved = false;
     loop {
         let rv = KeyPrompt::with_theme(&ColoredTheme::default())
             .with_text(&format!("Execute step {}?", chosen_cmd.name))
-            .items(&['y', 'n', 'p'])
+            .items(&['y', 'n', 'p', 'e'])
             .default(0)
             .interact()
             .expect("Inkjet: unable to read response");
         if rv == 'y' {
             break;
         } else if rv == 'p' {
-            match execute_command(chosen_cmd.clone(), inkfile_path, true, color, fixed_dir) {
-                Some(result) => {
-                    match result {
-                        Ok(exit_status) => {
-                            if exit_status.success() {
-                                eprintln!(); // empty space
-                                continue;
-                            } else {
-                                return (None, exit_status.code().unwrap_or(10), "unable to preview command (perhaps bat returned non-zero status)".to_string());
-                            }
-                        }
-                        Err(err) => {
-                            return (None, 10, err.to_string());
-                        }
+            match execute_command(
+                chosen_cmd.clone(),
+                &inkfile.path,
+                true,
+                color,
+                inkfile.config.fixed_dir,
+            ) {
+                Ok(Outcome::Previewed(Some(exit_status))) => {
+                    if exit_status.success() {
+                        eprintln!(); // empty space
+                        continue;
+                    } else {
+                        return (
+                            None,
+                            exit_status.code().unwrap_or(10),
+                            "unable to preview command (perhaps bat returned non-zero status)"
+                                .to_string(),
+                        );
                     }
                 }
+                Err(err) => {
+                    return (None, 10, err.to_string());
+                }
                 _ => {
                     return (None, 0, "".to_string());
                 }
             }
+        } else if rv == 'e' {
+            if !resolved {
+                chosen_cmd = prompt_params(chosen_cmd, remembered, color);
+                resolved = true;
+            }
+            if !edit_script(&mut chosen_cmd) {
+                continue;
+            }
+            if save_edits {
+                match inkfile.save_script(&original, &chosen_cmd.script.source) {
+                    Ok(path) => {
+                        // Saving again replaces the script that was just written
+                        if let Some(saved) = inkfile.find(command_path) {
+                            original = saved.clone();
+                        }
+                        eprintln!("Saved edits to {path}")
+                    }
+                    Err(err) => eprintln!("{} unable to save edits: {err}", utils::error_msg()),
+                }
+            } else {
+                eprintln!(
+                    "Edited step {}. The edit applies to this run only.",
+                    chosen_cmd.name
+                );
+            }
         } else {
             eprintln!("Skipping command {}", chosen_cmd.name);
             return (None, 0, "".to_string());
         }
     }
+    if resolved {
+        return (Some(chosen_cmd), 0, "".to_string());
+    }
+    (
+        Some(prompt_params(chosen_cmd, remembered, color)),
+        0,
+        "".to_string(),
+    )
+    // cov:end-include
+}
+
+/// Separates the header listing the values of a command from its script in the editor
+const EDIT_SEPARATOR: &str = "--- inkjet: edit the script below this line ---";
+
+/// Opens the script of a command in the user's editor. Returns true if the script was changed.
+/// A comment header above the script lists the values of the flags and args. Secret values are masked.
+pub(crate) fn edit_script(cmd: &mut CommandBlock) -> bool {
+    // cov:begin-include
+    let (extension, comment) = match cmd.script.executor.as_str() {
+        "" | "sh" | "bash" | "zsh" | "dash" => (".sh", "#"),
+        "js" | "javascript" => (".js", "//"),
+        "py" | "python" | "python3" => (".py", "#"),
+        "rb" | "ruby" => (".rb", "#"),
+        "ts" | "typescript" => (".ts", "//"),
+        "php" => (".php", "//"),
+        "go" => (".go", "//"),
+        _ => (".txt", "#"),
+    };
+    let mut text = String::new();
+    for flag in cmd.named_flags.iter().filter(|f| !f.val.is_empty()) {
+        let val = if flag.secret { "<secret>" } else { &flag.val };
+        text.push_str(&format!("{comment} {}={val}\n", flag.name));
+    }
+    for arg in cmd.args.iter().filter(|a| !a.val.is_empty()) {
+        text.push_str(&format!("{comment} {}={}\n", arg.name, arg.val));
+    }
+    text.push_str(&format!("{comment} {EDIT_SEPARATOR}\n"));
+    text.push_str(&cmd.script.source);
+    match Editor::new().extension(extension).edit(&text) {
+        Ok(Some(edited)) => {
+            let source = match edited.split_once(EDIT_SEPARATOR) {
+                Some((_, rest)) => rest.strip_prefix('\n').unwrap_or(rest).to_string(),
+                None => edited,
+            };
+            if source == cmd.script.source {
+                return false;
+            }
+            cmd.script.source = source;
+            true
+        }
+        Ok(None) => false,
+        Err(err) => {
+            eprintln!("{} unable to open an editor: {err}", utils::error_msg());
+            false
+        }
+    }
+    // cov:end-include
+}
+
+/// Asks before running a command with a `confirm` or `confirm_phrase` option.
+/// Returns the exit code to use if the command must not run.
+/// Without a terminal the command only runs if assume_yes is set or INKJET_YES is set.
+pub(crate) fn confirm_command(cmd: &CommandBlock, assume_yes: bool, color: bool) -> Option<i32> {
+    if cmd.confirm.is_empty() && cmd.confirm_phrase.is_empty() {
+        return None;
+    }
+    let env_yes = env::var(YES_VAR).is_ok_and(|v| !v.is_empty() && v != "0");
+    if assume_yes || env_yes {
+        return None;
+    }
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "{} {} asks for confirmation before it runs. Pass --yes or set {YES_VAR}=1 to run it without a terminal.",
+            utils::error_msg(),
+            cmd.name
+        );
+        return Some(NOT_CONFIRMED_CODE);
+    }
+    // cov:begin-include
+    let question = if cmd.confirm.is_empty() {
+        format!("Run {}?", cmd.name)
+    } else {
+        cmd.confirm.clone()
+    };
+    let confirmed = if cmd.confirm_phrase.is_empty() {
+        Confirmation::with_theme(&ColoredTheme::default())
+            .with_text(&question)
+            .default(false)
+            .interact()
+            .expect("Inkjet: unable to confirm command")
+    } else {
+        let phrase = if color {
+            color_print::cformat!("<bold>{}</bold>", cmd.confirm_phrase)
+        } else {
+            cmd.confirm_phrase.clone()
+        };
+        let typed: String = Input::with_theme(&ColoredTheme::default())
+            .with_prompt(&format!("{question} Type {phrase} to continue"))
+            .allow_empty(true)
+            .interact()
+            .expect("Inkjet: unable to read confirmation");
+        typed.trim() == cmd.confirm_phrase
+    };
+    if confirmed {
+        return None;
+    }
+    eprintln!("Did not run {}", cmd.name);
+    Some(1)
+    // cov:end-include
+}
+
+/// Prompt for the flags and args that were not given on the command line.
+/// The values in remembered, keyed by flag or arg name, are the defaults of the prompts.
+pub(crate) fn prompt_params(
+    mut chosen_cmd: CommandBlock,
+    remembered: &BTreeMap<String, String>,
+    color: bool,
+) -> CommandBlock {
+    // cov:begin-include
     for flag in &mut chosen_cmd.named_flags {
         if !flag.takes_value {
             if flag.name == "verbose" {
@@ -300,7 +974,7 @@ fn interactive_params(
             if flag.val != "true" {
                 let rv: bool = Confirmation::with_theme(&ColoredTheme::default())
                     .with_text(&format!("{}: Set {} option?", chosen_cmd.name, flag.name))
-                    .default(false)
+                    .default(remembered.get(&flag.name).is_some_and(|v| v == "true"))
                     .interact()
                     .expect("Inkjet: unable to confirm option");
                 if rv {
@@ -308,66 +982,148 @@ fn interactive_params(
                 }
             }
         } else if flag.val.is_empty() {
-            let mut rv: String;
-            loop {
-                let name = flag.name.clone();
-                rv = Input::with_theme(&ColoredTheme::default())
-                    .with_prompt(&format!(
-                        "{}: Enter option for {}{}",
-                        chosen_cmd.name,
-                        name,
-                        if flag.required { " *" } else { "" }
-                    ))
-                    .allow_empty(!flag.required)
-                    .interact()
-                    .expect("Inkjet: unable to read option");
-                if !flag.choices.is_empty() && !flag.choices.contains(&rv) {
-                    if color {
-                        eprintln!(
-                            "{} {} flag expects one of {:?}",
-                            utils::INVALID_MSG,
-                            flag.name,
-                            flag.choices
-                        );
+            // Secret flags are never remembered
+            let default = remembered.get(&flag.name).filter(|_| !flag.secret).cloned();
+            let prompt = format!(
+                "{}: Enter option for {}{}",
+                chosen_cmd.name,
+                flag.name,
+                if flag.required { " *" } else { "" }
+            );
+            flag.val = if !flag.choices.is_empty() {
+                prompt_choices(
+                    &prompt,
+                    &flag.choices,
+                    flag.multiple,
+                    flag.required,
+                    default.as_deref(),
+                )
+            } else if flag.multiple {
+                prompt_repeated(&prompt, &flag.name, flag.required, default)
+            } else {
+                loop {
+                    let rv = if flag.secret {
+                        PasswordInput::with_theme(&ColoredTheme::default())
+                            .with_prompt(&prompt)
+                            .allow_empty_password(!flag.required)
+                            .interact()
+                            .expect("Inkjet: unable to read option")
                     } else {
-                        eprintln!(
-                            "INVALID: {} flag expects one of {:?}",
-                            flag.name, flag.choices
-                        );
+                        Input::with_theme(&ColoredTheme::default())
+                            .with_prompt(&prompt)
+                            .allow_empty(!flag.required)
+                            .default(default.clone())
+                            .interact()
+                            .expect("Inkjet: unable to read option")
+                    };
+                    if is_invalid_number(flag.validate_as_number, &rv) {
+                        if color {
+                            eprintln!("{} {}", utils::INVALID_MSG, not_number_err_msg(&flag.name));
+                        } else {
+                            eprintln!("INVALID: {}", not_number_err_msg(&flag.name));
+                        }
+                        continue;
                     }
-                    continue;
+                    break rv;
                 }
-                if is_invalid_number(flag.validate_as_number, &rv) {
-                    if color {
-                        eprintln!("{} {}", utils::INVALID_MSG, not_number_err_msg(&name));
-                    } else {
-                        eprintln!("INVALID: {}", not_number_err_msg(&name));
-                    }
-                    continue;
-                } else {
-                    break;
-                };
-            }
-            flag.val = rv
+            };
         }
     }
     for arg in chosen_cmd.args.iter_mut() {
         if arg.val.is_empty() {
-            let rv: String = Input::with_theme(&ColoredTheme::default())
-                .with_prompt(&format!(
-                    "{}: Enter value for {}{}",
-                    chosen_cmd.name,
-                    arg.name,
-                    if arg.required { " *" } else { "" },
-                ))
-                .allow_empty(!arg.required)
-                .default(arg.default.clone())
-                .interact()
-                .expect("Inkjet: unable to read input");
-            arg.val = rv
-        }
-    }
-    (Some(chosen_cmd), 0, "".to_string())
+            let default = remembered
+                .get(&arg.name)
+                .cloned()
+                .or_else(|| arg.default.clone());
+            let prompt = format!(
+                "{}: Enter value for {}{}",
+                chosen_cmd.name,
+                arg.name,
+                if arg.required { " *" } else { "" },
+            );
+            arg.val = if arg.multiple {
+                prompt_repeated(&prompt, &arg.name, arg.required, default)
+            } else {
+                Input::with_theme(&ColoredTheme::default())
+                    .with_prompt(&prompt)
+                    .allow_empty(!arg.required)
+                    .default(default)
+                    .interact()
+                    .expect("Inkjet: unable to read input")
+            };
+        }
+    }
+    chosen_cmd
+    // cov:end-include
+}
+
+/// Shows a menu of the choices of a flag. Several can be checked if the flag accepts multiple values.
+/// An optional flag can be left unset, which is selected unless default names a choice.
+fn prompt_choices(
+    prompt: &str,
+    choices: &[String],
+    multiple: bool,
+    required: bool,
+    default: Option<&str>,
+) -> String {
+    // cov:begin-include
+    let defaults: Vec<&str> = default.unwrap_or_default().split_whitespace().collect();
+    if multiple {
+        let checked: Vec<bool> = choices
+            .iter()
+            .map(|c| defaults.contains(&c.as_str()))
+            .collect();
+        let picked = Checkboxes::with_theme(&ColoredTheme::default())
+            .with_prompt(prompt)
+            .items(choices)
+            .defaults(&checked)
+            .interact()
+            .expect("Inkjet: unable to read choices");
+        return picked
+            .iter()
+            .filter_map(|&i| choices.get(i).cloned())
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+    let mut items: Vec<&str> = choices.iter().map(|c| c.as_str()).collect();
+    if !required {
+        items.push("(none)");
+    }
+    // Without a remembered choice an optional flag starts on "(none)"
+    let selected = choices
+        .iter()
+        .position(|c| defaults.contains(&c.as_str()))
+        .unwrap_or(if required { 0 } else { choices.len() });
+    let picked = Select::with_theme(&ColoredTheme::default())
+        .with_prompt(prompt)
+        .items(&items)
+        .default(selected)
+        .interact()
+        .expect("Inkjet: unable to read choice");
+    choices.get(picked).cloned().unwrap_or_default()
+    // cov:end-include
+}
+
+/// Asks for values one at a time until an empty value is entered. The values are joined with spaces.
+fn prompt_repeated(prompt: &str, name: &str, required: bool, default: Option<String>) -> String {
+    // cov:begin-include
+    let first: String = Input::with_theme(&ColoredTheme::default())
+        .with_prompt(prompt)
+        .allow_empty(!required)
+        .default(default)
+        .interact()
+        .expect("Inkjet: unable to read input");
+    let mut values = vec![first];
+    while values.last().is_some_and(|v| !v.is_empty()) {
+        let next: String = Input::with_theme(&ColoredTheme::default())
+            .with_prompt(&format!("Enter another value for {name} (empty to finish)"))
+            .allow_empty(true)
+            .interact()
+            .expect("Inkjet: unable to read input");
+        values.push(next);
+    }
+    values.retain(|v| !v.is_empty());
+    values.join(" ")
     // cov:end-include
 }
 
@@ -387,9 +1143,31 @@ macro_rules! sset {
 #[derive(Default, Debug)]
 struct CustomOpts {
     interactive: bool,
+    /// No command was given, so pre_parse appended `default`
+    no_command: bool,
+    runbook: bool,
+    resume: bool,
+    session: bool,
+    save_edits: bool,
+    no_remember: bool,
+    forget: bool,
+    yes: bool,
     preview: bool,
     inkfile_opt: String,
     print_all: bool,
+    print_imports: bool,
+    check: bool,
+    check_format: String,
+    check_scripts: bool,
+    dump: bool,
+    dump_format: String,
+    bundle: bool,
+    man: bool,
+    docs: bool,
+    docs_format: String,
+    output: String,
+    install_completions: bool,
+    install_shell: String,
 }
 
 /// We must parse flags first to handle global flags and implicit defaults
@@ -401,7 +1179,16 @@ fn pre_parse(mut args: Vec<String>) -> (CustomOpts, Vec<String>) {
         "-V",
         "--version",
         "--inkjet-print-all",
-        "--inkjet-dynamic-completions"
+        "--inkjet-print-imports",
+        "--inkjet-check",
+        "--inkjet-dump",
+        "--inkjet-bundle",
+        "--inkjet-man",
+        "--inkjet-docs",
+        "--inkjet-install-completions",
+        "--inkjet-dynamic-completions",
+        "--runbook",
+        "--forget"
     ];
     // Loop through all args and parse
     let mut inkfile_index = 1000;
@@ -426,6 +1213,7 @@ fn pre_parse(mut args: Vec<String>) -> (CustomOpts, Vec<String>) {
             opts.inkfile_opt = canonical_path(arg);
             if i == args.len() - 1 {
                 default_index = 1000; // prevent duplicate default insertions
+                opts.no_command = true;
                 args.insert(i + 1, "default".to_string());
                 break;
             }
@@ -440,6 +1228,14 @@ fn pre_parse(mut args: Vec<String>) -> (CustomOpts, Vec<String>) {
             } else {
                 inkfile_index = i + 1
             }
+        } else if arg == "--session" {
+            opts.session = true;
+        } else if arg == "--save-edits" {
+            opts.save_edits = true;
+        } else if arg == "--no-remember" {
+            opts.no_remember = true;
+        } else if arg == "--yes" {
+            opts.yes = true;
         } else if arg == "--preview" || arg == "-p" {
             if !opts.preview {
                 opts.preview = true;
@@ -448,6 +1244,100 @@ fn pre_parse(mut args: Vec<String>) -> (CustomOpts, Vec<String>) {
             opts.print_all = true;
             default_index = 1000;
             break;
+        } else if arg == "--inkjet-print-imports" {
+            opts.print_imports = true;
+            default_index = 1000;
+            break;
+        } else if arg == "--inkjet-check" {
+            opts.check = true;
+            opts.check_format = "text".to_string();
+            let rest = args.get(i + 1..).unwrap_or_default();
+            for (j, flag) in rest.iter().enumerate() {
+                if let Some(format) = flag.strip_prefix("--format=") {
+                    opts.check_format = format.to_string();
+                } else if flag == "--format" {
+                    opts.check_format = rest.get(j + 1).cloned().unwrap_or_default();
+                } else if flag == "--scripts" {
+                    opts.check_scripts = true;
+                }
+            }
+            default_index = 1000;
+            break;
+        } else if arg == "--inkjet-dump" || arg.starts_with("--inkjet-dump=") {
+            opts.dump = true;
+            opts.dump_format = match arg.strip_prefix("--inkjet-dump=") {
+                Some(format) => format.to_string(),
+                None => args
+                    .get(i + 1)
+                    .filter(|f| !f.starts_with('-'))
+                    .cloned()
+                    .unwrap_or_else(|| "json".to_string()),
+            };
+            default_index = 1000;
+            break;
+        } else if arg == "--inkjet-docs" || arg.starts_with("--inkjet-docs=") {
+            opts.docs = true;
+            opts.docs_format = match arg.strip_prefix("--inkjet-docs=") {
+                Some(format) => format.to_string(),
+                None => args
+                    .get(i + 1)
+                    .filter(|f| !f.starts_with('-'))
+                    .cloned()
+                    .unwrap_or_else(|| "markdown".to_string()),
+            };
+            default_index = 1000;
+            break;
+        } else if arg == "--inkjet-install-completions"
+            || arg.starts_with("--inkjet-install-completions=")
+        {
+            opts.install_completions = true;
+            opts.install_shell = match arg.strip_prefix("--inkjet-install-completions=") {
+                Some(shell) => shell.to_string(),
+                None => args.get(i + 1).cloned().unwrap_or_default(),
+            };
+            default_index = 1000;
+            break;
+        } else if arg == "--forget" {
+            opts.forget = true;
+            for flag in args.get(i + 1..).unwrap_or_default() {
+                if !flag.starts_with('-') {
+                    opts.inkfile_opt = canonical_path(flag);
+                }
+            }
+            default_index = 1000;
+            break;
+        } else if arg == "--runbook" {
+            opts.runbook = true;
+            for flag in args.get(i + 1..).unwrap_or_default() {
+                if flag == "--resume" {
+                    opts.resume = true;
+                } else if flag == "--session" {
+                    opts.session = true;
+                } else if flag == "--save-edits" {
+                    opts.save_edits = true;
+                } else if flag == "--yes" {
+                    opts.yes = true;
+                } else if !flag.starts_with('-') {
+                    opts.inkfile_opt = canonical_path(flag);
+                }
+            }
+            default_index = 1000;
+            break;
+        } else if arg == "--inkjet-bundle" || arg == "--inkjet-man" {
+            opts.bundle = arg == "--inkjet-bundle";
+            opts.man = arg == "--inkjet-man";
+            let mut rest = args.get(i + 1..).unwrap_or_default().iter();
+            while let Some(flag) = rest.next() {
+                if let Some(output) = flag.strip_prefix("--output=") {
+                    opts.output = output.to_string();
+                } else if flag == "-o" || flag == "--output" {
+                    opts.output = rest.next().cloned().unwrap_or_default();
+                } else if !flag.starts_with('-') {
+                    opts.inkfile_opt = canonical_path(flag);
+                }
+            }
+            default_index = 1000;
+            break;
         } else if arg.ends_with(".md") && inkfile_index == 1000 {
             // we found a markdown filename without it being proceeded by `--inkfile`
             // we will insert that after the loop if required.
@@ -468,6 +1358,7 @@ fn pre_parse(mut args: Vec<String>) -> (CustomOpts, Vec<String>) {
     }
     if default_index <= args.len() {
         if default_index == 0 {
+            opts.no_command = true;
             args.push("default".to_string());
         } else {
             args.insert(default_index, "default".to_string());
@@ -503,7 +1394,25 @@ fn custom_inkfile_path_arg() -> Arg {
         .action(clap::ArgAction::Set)
 }
 /// Helper function to build a Command from a CommandBlock
-fn build_command_from_block(cmd_block: CommandBlock, opts: &CustomOpts, sort: bool) -> Command {
+fn build_command_from_block(
+    cmd_block: CommandBlock,
+    opts: &CustomOpts,
+    inkfile: &Inkfile,
+    sort: bool,
+) -> Command {
+    // Completion scripts run for the command without its subcommands
+    let completion_cmd = CommandBlock {
+        subcommands: vec![],
+        ..cmd_block.clone()
+    };
+    let value_completer = |script: Script| {
+        ArgValueCompleter::new(ScriptCompleter {
+            cmd: completion_cmd.clone(),
+            script,
+            inkfile_path: inkfile.path.clone(),
+            fixed_dir: inkfile.config.fixed_dir,
+        })
+    };
     let name = cmd_block.name;
     let desc = cmd_block.desc;
     let args = cmd_block.args;
@@ -519,7 +1428,7 @@ fn build_command_from_block(cmd_block: CommandBlock, opts: &CustomOpts, sort: bo
     // Process subcommands recursively
     if !subcommands.is_empty() {
         // Pass ownership of the subcommands
-        cmd = build_subcommands(cmd, opts, subcommands, sort);
+        cmd = build_subcommands(cmd, opts, inkfile, subcommands, sort);
         // If this parent command has no script source, require a subcommand.
         if script_source.is_empty() {
             cmd = cmd.subcommand_required(true);
@@ -553,6 +1462,9 @@ fn build_command_from_block(cmd_block: CommandBlock, opts: &CustomOpts, sort: bo
         } else {
             a.required
         });
+        if let Some(script) = a.complete {
+            arg = arg.add(value_completer(script));
+        }
         cmd = cmd.arg(arg);
     }
 
@@ -582,6 +1494,12 @@ fn build_command_from_block(cmd_block: CommandBlock, opts: &CustomOpts, sort: bo
             } else {
                 arg = arg.action(clap::ArgAction::Set);
             }
+            if !f.choices.is_empty() {
+                arg = arg.value_parser(ChoicesParser(f.choices));
+            }
+            if let Some(script) = f.complete {
+                arg = arg.add(value_completer(script));
+            }
         } else {
             arg = arg.action(clap::ArgAction::SetTrue);
         }
@@ -604,16 +1522,58 @@ fn build_command_from_block(cmd_block: CommandBlock, opts: &CustomOpts, sort: bo
     cmd
 }
 
+/// ChoicesParser accepts any value but reports the choices of a flag to clap for its help and completions.
+/// The value is validated by embed_arg_values so that the error matches the interactive mode.
+#[derive(Clone)]
+struct ChoicesParser(Vec<String>);
+
+impl TypedValueParser for ChoicesParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(self.0.iter().map(PossibleValue::new)))
+    }
+}
+
+/// ScriptCompleter completes the value of an arg or flag with the output of its `complete` script.
+struct ScriptCompleter {
+    cmd: CommandBlock,
+    script: Script,
+    inkfile_path: String,
+    fixed_dir: bool,
+}
+
+impl ValueCompleter for ScriptCompleter {
+    fn complete(&self, current: &OsStr) -> Vec<CompletionCandidate> {
+        let current = current.to_string_lossy();
+        completion_candidates(&self.cmd, &self.script, &self.inkfile_path, self.fixed_dir)
+            .into_iter()
+            .filter(|(value, _)| value.starts_with(current.as_ref()))
+            .map(|(value, desc)| CompletionCandidate::new(value).help(desc.map(Into::into)))
+            .collect()
+    }
+}
+
 /// Takes a `clap_app` and a parsed root command and recursively builds the CLI application
 fn build_subcommands(
     mut cli_app: Command,
     opts: &CustomOpts,
+    inkfile: &Inkfile,
     subcommands: Vec<CommandBlock>,
     sort: bool,
 ) -> Command {
     for c in subcommands {
         // Build a new Command from the CommandBlock
-        let mut subcmd = build_command_from_block(c, opts, sort);
+        let mut subcmd = build_command_from_block(c, opts, inkfile, sort);
         if sort {
             subcmd = subcmd.display_order(0);
         }
@@ -627,6 +1587,17 @@ fn build_subcommands(
 }
 /// finds the CommandBlock to execute based on supplied args. If the user input fails validation then
 /// the validation_error_msg property will be non-empty
+/// Returns the names of the subcommands that were used, i.e. `["services", "start"]`
+fn command_path(matches: &ArgMatches) -> Vec<String> {
+    let mut path = vec![];
+    let mut matches = matches;
+    while let Some((name, sub_matches)) = matches.subcommand() {
+        path.push(name.to_string());
+        matches = sub_matches;
+    }
+    path
+}
+
 fn find_command(matches: &ArgMatches, subcommands: &[CommandBlock]) -> Option<CommandBlock> {
     let mut command = None;
     // The child subcommand that was used
@@ -703,7 +1674,7 @@ fn embed_arg_values(mut cmd: CommandBlock, matches: &ArgMatches) -> CommandBlock
     cmd
 }
 /// returns true if flag is set and the string should parse as number and does not
-fn is_invalid_number(is_num: bool, raw_value: &str) -> bool {
+pub(crate) fn is_invalid_number(is_num: bool, raw_value: &str) -> bool {
     if !is_num || raw_value.is_empty() {
         return false;
     }
@@ -711,7 +1682,7 @@ fn is_invalid_number(is_num: bool, raw_value: &str) -> bool {
     raw_value.parse::<isize>().is_err() && raw_value.parse::<f32>().is_err()
 }
 
-fn not_number_err_msg(name: &str) -> String {
+pub(crate) fn not_number_err_msg(name: &str) -> String {
     format!("flag `{name}` expects a numerical value")
 }
 
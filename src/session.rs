@@ -0,0 +1,469 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::{process, thread, time};
+
+use crate::command::CommandBlock;
+use crate::executor::{
+    ExecutorRegistry, Outcome, build_child, display_path, execute_command, local_inkfile,
+    report_failure,
+};
+use crate::utils;
+
+/// Reads the path of each step from the control FIFO, sources it and writes its exit status to the status FIFO.
+/// Sourcing runs the step in the session shell itself, so `cd`, variables and functions carry over.
+/// Steps run with `set -e` like any other shell block. A failing command ends the shell with its status,
+/// which closes the status FIFO, so the failure is read from the exit status of the shell.
+/// After each step that succeeds, the exported variables and the directory are saved into the directory of the shell
+/// so that a new shell can pick up where the last one ended.
+const LOOP: &str = r#"exec 3<"$1" 4>"$2"
+__inkjet_dir=$3
+set --
+while IFS= read -r __inkjet_step <&3; do
+  set -e
+  . "$__inkjet_step"
+  set +e
+  export -p >"$__inkjet_dir/exports"
+  pwd >"$__inkjet_dir/pwd"
+  echo 0 >&4
+done"#;
+
+/// How long a new session shell may take to open its end of the named pipes
+const SPAWN_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// Session runs the shell blocks of successive steps in long-lived shell processes, one for each shell language.
+/// The working directory, variables and functions that one step sets are seen by the next.
+/// Other languages run in their own process as usual.
+///
+/// The shells inherit the terminal. Steps are passed to them through named pipes, so sessions require a unix system.
+#[derive(Debug, Default)]
+pub struct Session {
+    shells: HashMap<String, Shell>,
+    /// The state saved by shells that ended, which their replacements restore
+    saved: HashMap<String, Saved>,
+    steps: usize,
+}
+
+/// Saved is what a session shell saved after its last step that succeeded
+#[derive(Debug)]
+struct Saved {
+    /// The output of `export -p`
+    exports: String,
+    /// The working directory
+    dir: String,
+}
+
+#[derive(Debug)]
+struct Shell {
+    child: process::Child,
+    control: Option<File>,
+    status: BufReader<File>,
+    dir: PathBuf,
+}
+
+impl Session {
+    /// Creates a session. Shells are started when the first step of their language runs.
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    /// Returns the shell that runs a command in a session, or None if the command runs in its own process.
+    fn shell_of(cmd: &CommandBlock) -> Option<&str> {
+        if cmd.script.source.trim().starts_with("#!") {
+            return None;
+        }
+        match cmd.script.executor.as_str() {
+            "" => Some("sh"),
+            shell @ ("sh" | "bash" | "zsh" | "dash") => Some(shell),
+            _ => None,
+        }
+    }
+
+    /// Runs a command in the session. If preview is set or the command is not a shell block, it is run by `execute_command`.
+    pub fn execute(
+        &mut self,
+        cmd: CommandBlock,
+        inkfile_path: &str,
+        preview: bool,
+        color: bool,
+        fixed_dir: bool,
+    ) -> io::Result<Outcome> {
+        let Some(program) = Session::shell_of(&cmd).filter(|_| !preview) else {
+            return execute_command(cmd, inkfile_path, preview, color, fixed_dir);
+        };
+        let prepared = build_child(&cmd, inkfile_path, fixed_dir, &ExecutorRegistry::new())?;
+
+        let mut step = String::new();
+        let new_shell = !self.shells.contains_key(program);
+        if new_shell {
+            let shell = Shell::spawn(program)?;
+            match self.saved.remove(program) {
+                // Functions and variables that were not exported cannot be saved, so they are lost
+                Some(saved) => {
+                    eprintln!(
+                        "{} the {program} session ended, so a new one was started. Its directory and exported variables were restored, but functions and other variables were lost.",
+                        utils::info_msg()
+                    );
+                    let exports = shell.dir.join("exports");
+                    fs::write(&exports, saved.exports)?;
+                    // Some exported variables, such as those of the shell itself, are read only
+                    step.push_str(&format!(
+                        ". {} 2>/dev/null || :\ncd {}\n",
+                        quote(&exports.to_string_lossy()),
+                        quote(&saved.dir)
+                    ));
+                }
+                // Later steps keep the directory that earlier steps changed to
+                None => {
+                    if let Some(dir) = prepared.command.get_current_dir() {
+                        step.push_str(&format!("cd {}\n", quote(&dir.to_string_lossy())));
+                    }
+                }
+            }
+            self.shells.insert(program.to_string(), shell);
+        }
+        // A flag without a value is not set, so a value an earlier step gave it must not leak into this one
+        for flag in cmd.named_flags.iter().filter(|f| f.val.is_empty()) {
+            step.push_str(&format!("unset {}\n", flag.name.replace('-', "_")));
+        }
+        for (key, val) in prepared.command.get_envs() {
+            match val {
+                Some(val) => step.push_str(&format!(
+                    "export {}={}\n",
+                    key.to_string_lossy(),
+                    quote(&val.to_string_lossy())
+                )),
+                None => step.push_str(&format!("unset {}\n", key.to_string_lossy())),
+            }
+        }
+        step.push_str(&cmd.script.source);
+        step.push('\n');
+
+        self.steps += 1;
+        let shell = self
+            .shells
+            .get_mut(program)
+            .expect("Inkjet: session shell was started");
+        let status = shell.run(&step, self.steps);
+        let status = match status {
+            Ok(Some(status)) => status,
+            // The step exited the shell. The next step starts a new one that restores what it saved.
+            Ok(None) => {
+                let mut shell = self
+                    .shells
+                    .remove(program)
+                    .expect("Inkjet: session shell exists");
+                let status = shell.child.wait()?;
+                if let Some(saved) = shell.saved() {
+                    self.saved.insert(program.to_string(), saved);
+                }
+                shell.close();
+                status
+            }
+            Err(err) => {
+                if let Some(mut shell) = self.shells.remove(program) {
+                    shell.close();
+                }
+                return Err(err);
+            }
+        };
+        report_failure(
+            &cmd,
+            &display_path(local_inkfile(&cmd, inkfile_path)),
+            status,
+            None,
+            color,
+        );
+        Ok(Outcome::Exited(status))
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        for shell in self.shells.values_mut() {
+            shell.close();
+        }
+    }
+}
+
+impl Shell {
+    fn spawn(program: &str) -> io::Result<Shell> {
+        let dir = env::temp_dir().join(format!("inkjet-session-{}-{program}", process::id()));
+        fs::create_dir_all(&dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            // Step files hold the values of the flags, which may be secret
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        }
+        let control_path = dir.join("control");
+        let status_path = dir.join("status");
+        for fifo in [&control_path, &status_path] {
+            let created = process::Command::new("mkfifo").arg(fifo).status();
+            if !created.is_ok_and(|s| s.success()) {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(io::Error::other(
+                    "unable to create a named pipe for the session. Sessions require mkfifo.",
+                ));
+            }
+        }
+        let child = process::Command::new(program)
+            .arg("-c")
+            .arg(LOOP)
+            .arg(program)
+            .arg(&control_path)
+            .arg(&status_path)
+            .arg(&dir)
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(err);
+            }
+        };
+        match open_pipes(&mut child, &control_path, &status_path) {
+            Ok((control, status)) => Ok(Shell {
+                child,
+                control: Some(control),
+                status: BufReader::new(status),
+                dir,
+            }),
+            Err(err) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                let _ = fs::remove_dir_all(&dir);
+                Err(err)
+            }
+        }
+    }
+
+    /// Runs a step and returns its exit status. It is None if the step exited the shell.
+    fn run(&mut self, step: &str, n: usize) -> io::Result<Option<process::ExitStatus>> {
+        let path = self.dir.join(format!("step-{n}.sh"));
+        fs::write(&path, step)?;
+        let control = self
+            .control
+            .as_mut()
+            .ok_or_else(|| io::Error::other("the session has ended"))?;
+        writeln!(control, "{}", path.display())?;
+        control.flush()?;
+        let mut line = String::new();
+        let read = self.status.read_line(&mut line);
+        let _ = fs::remove_file(&path);
+        if read? == 0 {
+            return Ok(None);
+        }
+        let code: i32 = line.trim().parse().unwrap_or(1);
+        Ok(Some(exit_status(code)))
+    }
+
+    /// Returns what the loop saved after the last step that succeeded, if any did
+    fn saved(&self) -> Option<Saved> {
+        let exports = fs::read_to_string(self.dir.join("exports")).ok()?;
+        let dir = fs::read_to_string(self.dir.join("pwd")).ok()?;
+        Some(Saved {
+            exports,
+            dir: dir.trim_end_matches('\n').to_string(),
+        })
+    }
+
+    /// Ends the loop of the shell and removes its named pipes
+    fn close(&mut self) {
+        // The loop ends when the control pipe is closed
+        if self.control.take().is_some() {
+            let _ = self.child.wait();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Opens inkjet's ends of the named pipes of a new session shell.
+/// Opening a named pipe blocks until the other end is opened, so the pipes are opened on a thread
+/// while the shell is watched. An error is returned if the shell exits first or does not open them in time.
+fn open_pipes(
+    child: &mut process::Child,
+    control_path: &Path,
+    status_path: &Path,
+) -> io::Result<(File, File)> {
+    let (tx, rx) = mpsc::channel();
+    let (control, status) = (control_path.to_path_buf(), status_path.to_path_buf());
+    // These follow the order of the loop
+    thread::spawn(move || {
+        let opened = OpenOptions::new()
+            .write(true)
+            .open(&control)
+            .and_then(|control| Ok((control, File::open(&status)?)));
+        let _ = tx.send(opened);
+    });
+    let started = time::Instant::now();
+    let failure = loop {
+        match rx.recv_timeout(time::Duration::from_millis(20)) {
+            Ok(opened) => return opened,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                break io::Error::other("unable to open the session pipes");
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+        if let Some(status) = child.try_wait()? {
+            break io::Error::other(format!(
+                "the session shell exited with {status} before it started"
+            ));
+        }
+        if started.elapsed() > SPAWN_TIMEOUT {
+            break io::Error::other("the session shell did not start in time");
+        }
+    };
+    // Open the other ends so the thread is not left blocked on the pipes
+    if let Ok(_reader) = File::open(control_path) {
+        let _ = OpenOptions::new().write(true).open(status_path);
+    }
+    Err(failure)
+}
+
+/// Quotes a value for a POSIX shell
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(unix)]
+fn exit_status(code: i32) -> process::ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    process::ExitStatus::from_raw((code & 0xff) << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> process::ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    process::ExitStatus::from_raw(code as u32)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::inkfile::Inkfile;
+
+    #[test]
+    fn carries_state_between_steps() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let inkfile_path = dir.path().join("inkjet.md");
+        let contents = r#"
+## setup
+
+```sh
+mkdir -p build && cd build
+export TOKEN=secret
+greet() { echo "hello $1"; }
+```
+
+## verify
+
+```sh
+[ "$(basename "$PWD")" = build ] && [ "$TOKEN" = secret ] && greet world >/dev/null
+```
+
+## fail
+
+```sh
+false
+```
+
+## after
+
+```sh
+[ "$(basename "$PWD")" = build ] && [ "$TOKEN" = secret ]
+```
+"#;
+        fs::write(&inkfile_path, contents).unwrap();
+        let inkfile = Inkfile::load(&inkfile_path).expect("valid inkfile");
+        let path = inkfile.path.clone();
+        let mut session = Session::new();
+        // The shell that the failure ended is replaced by one with the same directory and exports
+        let steps = [
+            ("setup", true),
+            ("verify", true),
+            ("fail", false),
+            ("after", true),
+        ];
+        for (name, success) in steps {
+            let cmd = inkfile.find(name).expect("command exists").clone();
+            match session.execute(cmd, &path, false, false, true) {
+                Ok(Outcome::Exited(status)) => assert_eq!(status.success(), success, "{name}"),
+                other => panic!("{name} did not run: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn stops_at_failing_lines_and_unsets_empty_flags() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let inkfile_path = dir.path().join("inkjet.md");
+        let contents = r#"
+## broken
+
+```sh
+echo start
+false
+echo unreachable
+```
+
+## tag
+
+**OPTIONS**
+
+- flag: --name |string| The tag name
+
+```sh
+printf %s "${name-unset}" > tag.txt
+```
+"#;
+        fs::write(&inkfile_path, contents).unwrap();
+        let inkfile = Inkfile::load(&inkfile_path).expect("valid inkfile");
+        let path = inkfile.path.clone();
+        let mut session = Session::new();
+        let run = |session: &mut Session, cmd: CommandBlock| match session
+            .execute(cmd, &path, false, false, true)
+        {
+            Ok(Outcome::Exited(status)) => status.success(),
+            other => panic!("step did not run: {other:?}"),
+        };
+        let broken = inkfile.find("broken").expect("command exists").clone();
+        assert!(!run(&mut session, broken));
+
+        let mut tag = inkfile.find("tag").expect("command exists").clone();
+        if let Some(flag) = tag.named_flags.iter_mut().find(|f| f.name == "name") {
+            flag.val = "v1".to_string();
+        }
+        assert!(run(&mut session, tag.clone()));
+        let tagged = || fs::read_to_string(dir.path().join("tag.txt")).unwrap();
+        assert_eq!(tagged(), "v1");
+        for flag in &mut tag.named_flags {
+            flag.val.clear();
+        }
+        assert!(run(&mut session, tag));
+        assert_eq!(tagged(), "unset");
+    }
+
+    #[test]
+    fn reports_shells_that_exit_before_opening_the_pipes() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let (control, status) = (dir.path().join("control"), dir.path().join("status"));
+        for fifo in [&control, &status] {
+            let created = process::Command::new("mkfifo").arg(fifo).status();
+            assert!(created.is_ok_and(|s| s.success()));
+        }
+        let mut child = process::Command::new("sh")
+            .arg("-c")
+            .arg("exit 3")
+            .spawn()
+            .unwrap();
+        let err = open_pipes(&mut child, &control, &status).expect_err("the shell exited");
+        assert!(err.to_string().contains("before it started"), "{err}");
+    }
+}
@@ -1,16 +1,81 @@
 // Copyright 2025 Brandon Kalinowski (brandonkal)
 // SPDX-License-Identifier: MIT
 
+use std::env;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// The program name shown in message prefixes. Empty means `inkjet`.
+static PROGRAM_NAME: RwLock<String> = RwLock::new(String::new());
+
+/// Sets the program name shown in message prefixes, i.e. `ERROR (deploy):` for an inkfile named `deploy`.
+pub fn set_program_name(name: &str) {
+    if let Ok(mut program) = PROGRAM_NAME.write() {
+        *program = name.to_string();
+    }
+}
+
+/// returns the program name shown in message prefixes. It is `inkjet` unless an inkfile sets its own name.
+pub fn program_name() -> String {
+    match PROGRAM_NAME.read() {
+        Ok(name) if !name.is_empty() => name.clone(),
+        _ => "inkjet".to_string(),
+    }
+}
+
 /// returns INFO string (yellow if NO_COLOR is unset).
+#[deprecated(note = "use info_msg(), which names the program of a branded inkfile")]
 pub const INFO_MSG: &str =
     color_print::cstr!("<underline><yellow>INFO (inkjet):</yellow></underline>");
 
+/// returns INFO string (yellow).
+pub fn info_msg() -> String {
+    color_print::cformat!(
+        "<underline><yellow>INFO ({}):</yellow></underline>",
+        program_name()
+    )
+}
+
 /// returns WARNING string (yellow if NO_COLOR is unset).
+#[deprecated(note = "use warning_msg(), which names the program of a branded inkfile")]
 pub const WARNING_MSG: &str =
     color_print::cstr!("<underline><yellow>WARNING (inkjet):</yellow></underline>");
 
+/// returns WARNING string (yellow).
+pub fn warning_msg() -> String {
+    color_print::cformat!(
+        "<underline><yellow>WARNING ({}):</yellow></underline>",
+        program_name()
+    )
+}
+
 /// returns ERROR string (red if NO_COLOR is unset).
+#[deprecated(note = "use error_msg(), which names the program of a branded inkfile")]
 pub const ERROR_MSG: &str = color_print::cstr!("<underline><red>ERROR (inkjet):</red></underline>");
 
+/// returns ERROR string (red).
+pub fn error_msg() -> String {
+    color_print::cformat!(
+        "<underline><red>ERROR ({}):</red></underline>",
+        program_name()
+    )
+}
+
+/// returns a message prefix without color, i.e. `ERROR (inkjet):` for a level of `ERROR`.
+pub fn plain_msg(level: &str) -> String {
+    format!("{level} ({}):", program_name())
+}
+
+/// returns the directory named by an XDG variable such as `XDG_CACHE_HOME`, or default below the home directory if it is unset.
+pub fn xdg_dir(var: &str, default: &str) -> Option<PathBuf> {
+    match env::var_os(var).filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => {
+            let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+            Some(PathBuf::from(home).join(default))
+        }
+    }
+}
+
 /// returns INVALID string (red if NO_COLOR is unset).
 pub const INVALID_MSG: &str = color_print::cstr!("<underline><red>INVALID:</red></underline>");
@@ -67,6 +67,11 @@ impl Printer {
             syntax_set: &self.syntax_set,
         };
 
+        // Front matter holds directives for inkjet, not prose
+        let input = match crate::parser::front_matter_range(input) {
+            Some(range) => input.get(range.end..).unwrap_or_default(),
+            None => input,
+        };
         // Create parser
         let parser = create_markdown_parser(input);
 
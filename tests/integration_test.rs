@@ -4,7 +4,8 @@
 use std::path::PathBuf;
 
 use assert_cmd::prelude::*;
-use predicates::str::contains;
+use predicates::prelude::*;
+use predicates::str::{contains, is_match};
 
 mod common;
 pub use common::*;
@@ -150,7 +151,10 @@ echo "Should not print $b"
         .stderr(contains(
             "Invalid flag type 'invalid' Expected string | number | bool.",
         ))
-        .failure();
+        .stderr(contains("inkjet.md:4:12"))
+        .stderr(contains("- flag: -b |invalid| An invalid type"))
+        .stderr(contains("^^^^^^^^^"))
+        .code(78);
 }
 
 #[test]
@@ -239,6 +243,930 @@ ls -1 ls-test
         .success();
 }
 
+#[test]
+fn front_matter_directives() {
+    let (_temp, inkfile_path) = common::inkfile(
+        r#"+++
+sort = true
++++
+
+## zebra
+
+```
+echo "zebra"
+```
+
+## apple
+
+```
+echo "inkjet_sort: false"
+```
+"#,
+    );
+
+    common::run_inkjet(&inkfile_path)
+        .arg("--help")
+        .assert()
+        .stdout(is_match("(?s)apple.*zebra").unwrap())
+        .stderr(contains("deprecated").count(0))
+        .success();
+}
+
+#[test]
+fn inline_directives_are_deprecated() {
+    common::run_binary()
+        .current_dir("tests/merge")
+        .cli("echo")
+        .assert()
+        .stderr(contains(
+            "The inline directive \"inkjet_import: all\" is deprecated",
+        ))
+        .stdout(contains("Hello"))
+        .success();
+}
+
+#[test]
+fn check_reports_problems() {
+    let (_temp, inkfile_path) = common::inkfile(
+        r#"
+## build
+
+**OPTIONS**
+
+- flag: -v --version Print the version
+
+```
+echo "build"
+```
+"#,
+    );
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-check")
+        .assert()
+        .stdout(contains(
+            "inkjet.md:2:1: command \"build\": flags \"version\" and \"verbose\" both use the short flag -v",
+        ))
+        .code(78);
+    common::run_inkjet(&inkfile_path)
+        .cli("--inkjet-check --format json")
+        .assert()
+        .stdout(contains(r#""severity": "error""#))
+        .stdout(contains(r#""command": "build""#))
+        .stdout(contains(r#""line": 2"#))
+        .code(78);
+}
+
+#[test]
+fn check_passes_valid_inkfile() {
+    let (_temp, inkfile_path) = common::inkfile("## build\n\n```\necho build\n```\n");
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-check")
+        .assert()
+        .stdout(contains("No problems found"))
+        .success();
+}
+
+#[test]
+fn check_scripts_reports_syntax_errors() {
+    let (temp, inkfile_path) = common::inkfile("## broken\n\n```sh\necho start\nif then\n```\n");
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-check")
+        .assert()
+        .stdout(contains("No problems found"))
+        .success();
+    common::run_inkjet(&inkfile_path)
+        .cli("--inkjet-check --scripts")
+        .assert()
+        .stdout(contains("inkjet.md:5:1: command \"broken\": sh:"))
+        .code(78);
+
+    // A bundled executable checks the inkfile it carries
+    let output = temp.path().join("broken");
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-bundle")
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    std::process::Command::new(&output)
+        .cli("--inkjet-check --scripts")
+        .assert()
+        .stdout(contains(":5:1: command \"broken\": sh:"))
+        .code(78);
+}
+
+#[test]
+fn maps_script_errors_to_inkfile_lines() {
+    let (_temp, inkfile_path) = common::inkfile(
+        "## fail\n\n```sh\necho start\nnot_a_real_command_xyz\necho unreachable\n```\n",
+    );
+    common::run_inkjet(&inkfile_path)
+        .arg("fail")
+        .assert()
+        .stdout(contains("start"))
+        .stderr(contains("inkjet.md:5: not_a_real_command_xyz"))
+        .stderr(contains(
+            r#"inkjet.md:4: command "fail" exited with status 127 (last error at line 5)"#,
+        ))
+        .code(127);
+}
+
+#[test]
+fn does_not_wait_for_background_jobs_holding_stderr() {
+    let (_temp, inkfile_path) = common::inkfile(
+        "## serve\n\n```sh\n(sleep 5; echo late >&2) >/dev/null &\necho started\n```\n",
+    );
+    let started = std::time::Instant::now();
+    common::run_inkjet(&inkfile_path)
+        .arg("serve")
+        .assert()
+        .stdout(contains("started"))
+        .success();
+    assert!(started.elapsed() < std::time::Duration::from_secs(4));
+}
+
+#[test]
+fn dumps_command_tree_as_json() {
+    let (_temp, inkfile_path) = common::inkfile(
+        r#"## deploy//d (env)
+
+> Deploy the app
+
+**OPTIONS**
+
+- region
+  - flag: --region
+  - type: string
+  - desc: The region
+  - choices: us, eu
+  - required
+
+```bash
+echo "$env"
+```
+"#,
+    );
+    let output = common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-dump")
+        .output()
+        .expect("inkjet should run");
+    assert!(output.status.success());
+    let dump: serde_json::Value = serde_json::from_slice(&output.stdout).expect("valid json");
+    assert_eq!(dump["schema_version"], 1);
+    let deploy = &dump["root"]["subcommands"][0];
+    assert_eq!(deploy["name"], "deploy");
+    assert_eq!(deploy["aliases"], serde_json::json!(["d"]));
+    assert_eq!(deploy["args"][0]["name"], "env");
+    assert_eq!(deploy["script"]["executor"], "bash");
+    assert_eq!(deploy["script"]["line"], 15);
+    let region = &deploy["named_flags"][0];
+    assert_eq!(region["long"], "region");
+    assert_eq!(region["choices"], serde_json::json!(["us", "eu"]));
+    assert_eq!(region["required"], true);
+    assert!(region.get("val").is_none());
+}
+
+#[test]
+fn dumps_command_tree_as_yaml() {
+    let (_temp, inkfile_path) = common::inkfile("## build\n\n```\necho build\n```\n");
+    common::run_inkjet(&inkfile_path)
+        .cli("--inkjet-dump yaml")
+        .assert()
+        .stdout(contains("schema_version: 1"))
+        .stdout(contains("name: build"))
+        .success();
+    common::run_inkjet(&inkfile_path)
+        .cli("--inkjet-dump=toml")
+        .assert()
+        .stderr(contains("Unsupported format: toml"))
+        .code(2);
+}
+
+#[test]
+fn bundles_inkfile_into_standalone_executable() {
+    let (temp, inkfile_path) = common::inkfile(
+        r#"+++
+name = "greeter"
+version = "1.2.3"
++++
+
+> Says hello to people
+
+## hello (name)
+
+> Greet someone
+
+```sh
+echo "Hello $name"
+$INKJET bye
+```
+
+## bye
+
+```sh
+echo "Bye"
+```
+"#,
+    );
+    let output = temp.path().join("greeter");
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-bundle")
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    // The bundled executable does not need the markdown file
+    std::fs::remove_file(&inkfile_path).unwrap();
+
+    let other_dir = assert_fs::TempDir::new().unwrap();
+    std::process::Command::new(&output)
+        .current_dir(other_dir.path())
+        .cli("hello world")
+        .assert()
+        .stdout(contains("Hello world\nBye"))
+        .success();
+    std::process::Command::new(&output)
+        .arg("--version")
+        .assert()
+        .stdout("greeter 1.2.3\n");
+    std::process::Command::new(&output)
+        .arg("--help")
+        .assert()
+        .stdout(contains("Says hello to people"))
+        .stdout(contains("Usage: greeter"))
+        .stdout(contains("Brandon Kalinowski").not());
+}
+
+#[test]
+fn bundles_imports_without_build_paths() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    std::fs::write(
+        temp.path().join("inkjet.md"),
+        "+++\nimport = [\"./services/api/inkjet.md as api\"]\n+++\n\n## build\n\n```sh\necho built\n```\n",
+    )
+    .unwrap();
+    std::fs::create_dir_all(temp.path().join("services/api")).unwrap();
+    std::fs::write(
+        temp.path().join("services/api/inkjet.md"),
+        "## serve\n\n```sh\necho \"ink=$INK dir=$INK_DIR imported=$INKJET_IMPORTED\"\n```\n",
+    )
+    .unwrap();
+    let out_dir = assert_fs::TempDir::new().unwrap();
+    let output = out_dir.path().join("tool");
+    common::run_inkjet(&temp.path().join("inkjet.md"))
+        .arg("--inkjet-bundle")
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    let bytes = std::fs::read(&output).unwrap();
+    let build_dir = temp.path().to_string_lossy().into_owned();
+    assert!(
+        !bytes
+            .windows(build_dir.len())
+            .any(|w| w == build_dir.as_bytes()),
+        "the bundle contains {build_dir}"
+    );
+
+    // Imported commands call and live next to the executable, not the inkfiles it was built from
+    let exe = std::fs::canonicalize(&output).unwrap();
+    let exe_dir = exe.parent().unwrap();
+    std::process::Command::new(&output)
+        .cli("api serve")
+        .assert()
+        .stdout(format!(
+            "ink={} dir={} imported=true\n",
+            exe.display(),
+            exe_dir.display()
+        ))
+        .success();
+
+    // The library reads the bundle too
+    let bundled = inkjet::Inkfile::load(&output).unwrap();
+    assert_eq!(bundled.path, exe.to_string_lossy());
+    assert!(bundled.find("api serve").is_some());
+}
+
+#[test]
+fn bundle_requires_output() {
+    let (_temp, inkfile_path) = common::inkfile("## build\n\n```\necho build\n```\n");
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-bundle")
+        .assert()
+        .stderr(contains("requires an output path"))
+        .code(2);
+}
+
+#[test]
+fn brands_help_version_and_messages() {
+    let (_temp, inkfile_path) = common::inkfile(
+        r#"+++
+name = "deploy"
+version = "3.0.0"
+about = "Deploys the platform services"
+author = "Platform Team"
++++
+
+## fail
+
+```sh
+exit 3
+```
+"#,
+    );
+    common::run_inkjet(&inkfile_path)
+        .arg("--help")
+        .assert()
+        .stdout(contains("Deploys the platform services\nPlatform Team\n"))
+        .stdout(contains("Usage: deploy"))
+        .stdout(contains("Inkjet parser").not())
+        .success();
+    common::run_inkjet(&inkfile_path)
+        .arg("--version")
+        .assert()
+        .stdout("deploy 3.0.0\n");
+    common::run_inkjet(&inkfile_path)
+        .command("fail")
+        .assert()
+        .stderr(contains("ERROR (deploy):"))
+        .code(3);
+    common::run_inkjet(&inkfile_path)
+        .cli("inkjet-dynamic-completions bash")
+        .assert()
+        .stderr(contains("_deploy()"))
+        .success();
+}
+
+const REFERENCE_INKFILE: &str = r#"+++
+name = "deploy"
++++
+# deploy
+
+> Deploys things
+
+## services
+
+> Manage services
+
+### services start (service)
+
+> Start a service
+
+**OPTIONS**
+
+- flag: --env |string| Target environment
+
+```sh
+echo "starting $service"
+```
+"#;
+
+#[test]
+fn generates_man_pages() {
+    let (temp, inkfile_path) = common::inkfile(REFERENCE_INKFILE);
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-man")
+        .assert()
+        .stdout(contains(".TH deploy 1"))
+        .stdout(contains("Deploys things"))
+        .stdout(contains("deploy\\-services(1)"))
+        .success();
+
+    let man_dir = temp.path().join("man");
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-man")
+        .arg("-o")
+        .arg(&man_dir)
+        .assert()
+        .success();
+    let page = std::fs::read_to_string(man_dir.join("deploy-services-start.1")).unwrap();
+    assert!(page.contains("Target environment"));
+}
+
+#[test]
+fn generates_markdown_and_html_reference() {
+    let (_temp, inkfile_path) = common::inkfile(REFERENCE_INKFILE);
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-docs")
+        .assert()
+        .stdout(contains("## deploy services start\n\nStart a service\n"))
+        .stdout(contains(
+            "| `--env <env>` | string | no |  | Target environment |",
+        ))
+        .success();
+    common::run_inkjet(&inkfile_path)
+        .arg("--inkjet-docs=html")
+        .assert()
+        .stdout(contains("<h2>deploy services start</h2>"))
+        .success();
+    common::run_inkjet(&inkfile_path)
+        .cli("--inkjet-docs pdf")
+        .assert()
+        .stderr(contains("Unsupported format: pdf"))
+        .code(2);
+}
+
+const COMPLETION_INKFILE: &str = r#"
+## checkout (branch)
+
+**OPTIONS**
+
+- color
+  - flag: --color
+  - type: string
+  - choices: red, blue
+- remote
+  - flag: --remote
+  - type: string
+  - complete: printf 'origin\tThe main remote\nupstream\n'
+
+```sh
+echo "checkout $branch"
+```
+
+```sh complete branch
+printf 'main\nfeature-a\nfeature-b\n'
+```
+"#;
+
+/// Requests completions of the words from clap's dynamic engine
+fn complete(inkfile_path: &std::path::Path, words: &[&str]) -> assert_cmd::assert::Assert {
+    common::run_binary()
+        .env("INKJET_COMPLETE", "fish")
+        .arg("--")
+        .arg("inkjet")
+        .arg("--inkfile")
+        .arg(inkfile_path)
+        .args(words)
+        .assert()
+        .success()
+}
+
+#[test]
+fn completes_values_dynamically() {
+    let (_temp, inkfile_path) = common::inkfile(COMPLETION_INKFILE);
+    complete(&inkfile_path, &["ch"]).stdout(contains("checkout"));
+    complete(&inkfile_path, &["checkout", "feat"]).stdout("feature-a\nfeature-b\n");
+    complete(&inkfile_path, &["checkout", "--remote", ""])
+        .stdout("origin\tThe main remote\nupstream\n");
+    complete(&inkfile_path, &["checkout", "--color", ""]).stdout("red\nblue\n");
+
+    let empty_dir = assert_fs::TempDir::new().unwrap();
+    common::run_binary()
+        .current_dir(empty_dir.path())
+        .env("INKJET_COMPLETE", "bash")
+        .assert()
+        .stdout(contains("_clap_complete_inkjet"))
+        .success();
+}
+
+#[test]
+fn caches_the_parse_for_completions_and_help() {
+    let (_temp, inkfile_path) = common::inkfile(COMPLETION_INKFILE);
+    let cache_home = assert_fs::TempDir::new().unwrap();
+    let help = || {
+        common::run_inkjet(&inkfile_path)
+            .env("XDG_CACHE_HOME", cache_home.path())
+            .env_remove("INKJET_NO_CACHE")
+            .cli("--help")
+            .assert()
+            .success()
+    };
+    help().stdout(contains("checkout"));
+    let entries: Vec<_> = std::fs::read_dir(cache_home.path().join("inkjet"))
+        .unwrap()
+        .flatten()
+        .map(|e| e.path())
+        .collect();
+    let [entry] = entries.as_slice() else {
+        panic!("expected one cache entry: {entries:?}");
+    };
+
+    // A second run reads the tree from the entry instead of the inkfile
+    let cached = std::fs::read_to_string(entry).unwrap();
+    std::fs::write(entry, cached.replace("checkout", "cached")).unwrap();
+    help().stdout(contains("cached"));
+    common::run_inkjet(&inkfile_path)
+        .env("XDG_CACHE_HOME", cache_home.path())
+        .env("INKJET_NO_CACHE", "1")
+        .cli("--help")
+        .assert()
+        .stdout(contains("checkout"))
+        .success();
+
+    // Editing the inkfile invalidates the entry
+    let edited = COMPLETION_INKFILE.replace("## checkout", "## switch");
+    std::fs::write(&inkfile_path, edited).unwrap();
+    help().stdout(contains("switch").and(contains("cached").not()));
+}
+
+#[test]
+fn runbook_without_commands() {
+    let (_temp, inkfile_path) = common::inkfile("# Notes\n\n> Nothing to run here\n");
+    common::run_inkjet(&inkfile_path)
+        .cli("--runbook")
+        .assert()
+        .stderr(contains("has no commands to run"))
+        .success();
+}
+
+const CONFIRM_INKFILE: &str = r#"
+## drop
+
+**OPTIONS**
+
+- confirm: "This deletes prod data. Continue?"
+
+```sh
+echo "dropped"
+```
+"#;
+
+#[test]
+fn refuses_to_run_unconfirmed_commands_without_a_terminal() {
+    let (_temp, inkfile_path) = common::inkfile(CONFIRM_INKFILE);
+    common::run_inkjet(&inkfile_path)
+        .env_remove("INKJET_YES")
+        .cli("drop")
+        .assert()
+        .code(77)
+        .stderr(contains("drop asks for confirmation before it runs"))
+        .stdout(contains("dropped").not());
+}
+
+#[test]
+fn runs_confirmed_commands_with_yes() {
+    let (_temp, inkfile_path) = common::inkfile(CONFIRM_INKFILE);
+    common::run_inkjet(&inkfile_path)
+        .env_remove("INKJET_YES")
+        .cli("--yes drop")
+        .assert()
+        .stdout(contains("dropped"))
+        .success();
+    common::run_inkjet(&inkfile_path)
+        .env_remove("INKJET_YES")
+        .cli("drop --yes")
+        .assert()
+        .stdout(contains("dropped"))
+        .success();
+    common::run_inkjet(&inkfile_path)
+        .env("INKJET_YES", "1")
+        .cli("drop")
+        .assert()
+        .stdout(contains("dropped"))
+        .success();
+}
+
+#[test]
+fn forget_without_answers() {
+    let (_temp, inkfile_path) = common::inkfile("## build\n\n```sh\necho built\n```\n");
+    let data = assert_fs::TempDir::new().unwrap();
+    common::run_inkjet(&inkfile_path)
+        .env("XDG_DATA_HOME", data.path())
+        .cli("--forget")
+        .assert()
+        .stderr(contains("No answers are remembered for"))
+        .stdout(contains("built").not())
+        .success();
+}
+
+#[test]
+fn installs_completion_wrappers() {
+    let home = assert_fs::TempDir::new().unwrap();
+    common::run_binary()
+        .current_dir(home.path())
+        .env("HOME", home.path())
+        .env_remove("XDG_DATA_HOME")
+        .env_remove("XDG_CONFIG_HOME")
+        .cli("--inkjet-install-completions bash")
+        .assert()
+        .stderr(contains("Installed bash completions for inkjet"))
+        .success();
+    let wrapper = home
+        .path()
+        .join(".local/share/bash-completion/completions/inkjet");
+    let wrapper = std::fs::read_to_string(wrapper).unwrap();
+    assert!(wrapper.contains("source <(INKJET_COMPLETE=bash inkjet)"));
+    assert!(!wrapper.contains("/tmp"));
+
+    let config = home.path().join("config");
+    common::run_binary()
+        .current_dir(home.path())
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", &config)
+        .cli("--inkjet-install-completions nushell")
+        .assert()
+        .success();
+    assert!(config.join("nushell/autoload/inkjet.nu").is_file());
+
+    common::run_binary()
+        .cli("--inkjet-install-completions tcsh")
+        .assert()
+        .stderr(contains("Unsupported shell: tcsh"))
+        .code(2);
+}
+
+#[test]
+fn generates_static_completions_for_elvish_and_nushell() {
+    let (_temp, inkfile_path) = common::inkfile(COMPLETION_INKFILE);
+    common::run_inkjet(&inkfile_path)
+        .cli("inkjet-dynamic-completions elvish")
+        .assert()
+        .stderr(contains("edit:completion:arg-completer"))
+        .success();
+    common::run_inkjet(&inkfile_path)
+        .cli("inkjet-dynamic-completions nushell")
+        .assert()
+        .stderr(contains("export extern \"inkjet checkout\""))
+        .success();
+}
+
+#[test]
+fn lists_choices_in_help() {
+    let (_temp, inkfile_path) = common::inkfile(COMPLETION_INKFILE);
+    common::run_inkjet(&inkfile_path)
+        .cli("checkout --help")
+        .assert()
+        .stdout(contains("[possible values: red, blue]"))
+        .success();
+}
+
+mod imports {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    const API_INKFILE: &str = r#"> API service tasks
+
+## serve
+
+> Start the API
+
+```
+echo "serving from $(basename "$(pwd)")"
+```
+"#;
+
+    fn project(main: &str) -> assert_fs::TempDir {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        temp_dir.child("inkjet.md").write_str(main).unwrap();
+        temp_dir
+            .child("services/api/inkjet.md")
+            .write_str(API_INKFILE)
+            .unwrap();
+        temp_dir
+    }
+
+    #[test]
+    fn mounts_under_namespace_with_own_directory() {
+        let temp_dir = project(
+            r#"+++
+import = ["./services/api/inkjet.md as api"]
++++
+
+## build
+
+```
+echo "building"
+```
+"#,
+        );
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .cli("api serve")
+            .assert()
+            .stdout(contains("serving from api"))
+            .success();
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .cli("api --help")
+            .assert()
+            .stdout(contains("API service tasks"))
+            .success();
+    }
+
+    #[test]
+    fn explicit_import_is_not_merged_again_by_all() {
+        let temp_dir = project(
+            r#"+++
+import = ["all", "./services/api/inkjet.md as api"]
++++
+
+## build
+
+```
+echo "building"
+```
+"#,
+        );
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .arg("serve")
+            .assert()
+            .stderr(contains("unrecognized subcommand"))
+            .failure();
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .cli("api serve")
+            .assert()
+            .stdout(contains("serving from api"))
+            .success();
+    }
+
+    #[test]
+    fn mounts_the_script_and_flags_of_the_imported_root() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        temp_dir
+            .child("inkjet.md")
+            .write_str("+++\nimport = [\"./tools.md as tools\"]\n+++\n\n## build\n\n```\necho building\n```\n")
+            .unwrap();
+        temp_dir
+            .child("tools.md")
+            .write_str(
+                r#"# tools//t
+
+> Shared tools
+
+**OPTIONS**
+
+- flag: --target |string| What to run on
+
+```
+echo "tools on $target"
+```
+
+## lint
+
+```
+echo "linting"
+```
+"#,
+            )
+            .unwrap();
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .cli("t --target api")
+            .assert()
+            .stdout(contains("tools on api"))
+            .success();
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .cli("tools lint")
+            .assert()
+            .stdout(contains("linting"))
+            .success();
+    }
+
+    #[test]
+    fn file_found_by_all_is_not_imported_again() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        temp_dir
+            .child("inkjet.md")
+            .write_str("+++\nimport = [\"all\"]\n+++\n\n## build\n\n```\necho building\n```\n")
+            .unwrap();
+        temp_dir
+            .child("a/inkjet.md")
+            .write_str("## serve\n\n```\necho serving\n```\n")
+            .unwrap();
+        temp_dir
+            .child("b/inkjet.md")
+            .write_str("+++\nimport = [\"../a/inkjet.md as api\"]\n+++\n\n## deploy\n\n```\necho deploying\n```\n")
+            .unwrap();
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .arg("serve")
+            .assert()
+            .stdout(contains("serving"))
+            .stderr(contains("a/inkjet.md is already imported"))
+            .success();
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .cli("api serve")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn cache_notices_new_inkfiles_and_ignore_changes_below_all() {
+        let temp_dir =
+            project("+++\nimport = [\"all\"]\n+++\n\n## build\n\n```\necho building\n```\n");
+        temp_dir.child(".gitignore").write_str("/web\n").unwrap();
+        let cache_home = assert_fs::TempDir::new().unwrap();
+        let help = || {
+            common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+                .env("XDG_CACHE_HOME", cache_home.path())
+                .env_remove("INKJET_NO_CACHE")
+                .cli("--help")
+                .assert()
+                .success()
+        };
+        help().stdout(contains("serve").and(contains("deploy").not()));
+
+        // A new inkfile in a directory that did not contribute before
+        temp_dir
+            .child("services/web/inkjet.md")
+            .write_str("## deploy\n\n```\necho deploying\n```\n")
+            .unwrap();
+        help().stdout(contains("deploy"));
+
+        // Editing an ignore file changes what is discovered
+        temp_dir
+            .child("web/inkjet.md")
+            .write_str("## publish\n\n```\necho publishing\n```\n")
+            .unwrap();
+        help().stdout(contains("publish").not());
+        temp_dir
+            .child(".gitignore")
+            .write_str("# nothing ignored\n")
+            .unwrap();
+        help().stdout(contains("publish"));
+    }
+
+    #[test]
+    fn all_skips_ignored_and_excluded_paths() {
+        let temp_dir = project(
+            r#"+++
+import = ["all"]
+import_exclude = ["vendor"]
+import_max_depth = 3
++++
+
+## build
+
+```
+echo "building"
+```
+"#,
+        );
+        temp_dir
+            .child(".gitignore")
+            .write_str("node_modules\n")
+            .unwrap();
+        for ignored in [
+            "node_modules/dep/inkjet.md",
+            "vendor/lib/inkjet.md",
+            "deep/a/b/c/inkjet.md",
+        ] {
+            temp_dir
+                .child(ignored)
+                .write_str("## ignored\n\n```\necho ignored\n```\n")
+                .unwrap();
+        }
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .arg("--inkjet-print-imports")
+            .assert()
+            .stdout(is_match(r"^\S*services/api/inkjet.md\n$").unwrap())
+            .success();
+    }
+
+    #[test]
+    fn merges_commands_across_files() {
+        let temp_dir = project(
+            r#"+++
+import = ["all"]
++++
+
+## serve
+
+> Serve from the root
+
+```
+echo "root serve"
+```
+
+## other
+
+```
+echo "other"
+```
+"#,
+        );
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .arg("serve")
+            .assert()
+            .stdout(contains("serving from api"))
+            .stderr(is_match(r#"Command "serve" in \S*services/api/inkjet.md replaces the script defined in \S*/inkjet.md\n"#).unwrap())
+            .stderr(contains("replaces the description defined in"))
+            .success();
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .arg("other")
+            .assert()
+            .stdout(contains("other"))
+            .success();
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        temp_dir
+            .child("inkjet.md")
+            .write_str("+++\nimport = [\"./a.md as a\"]\n+++\n\n## one\n\n```\necho one\n```\n")
+            .unwrap();
+        temp_dir
+            .child("a.md")
+            .write_str(
+                "+++\nimport = [\"./inkjet.md as root\"]\n+++\n\n## two\n\n```\necho two\n```\n",
+            )
+            .unwrap();
+        common::run_inkjet(&temp_dir.path().join("inkjet.md"))
+            .arg("one")
+            .assert()
+            .stderr(contains("Import cycle detected"))
+            .code(78);
+    }
+}
+
 // Using current_dir(common::temp_path()) to make sure the default inkjet.md can't be found
 mod when_no_inkfile_found_in_current_directory {
     use super::*;
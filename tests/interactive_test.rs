@@ -41,6 +41,8 @@ mod interactive {
         p.send_line("")?;
         p.exp_string("Enter value for extras")?;
         p.send_line("--extra1=1 --extra2 extra3")?;
+        p.exp_string("Enter another value for extras (empty to finish)")?;
+        p.send_line("")?;
         p.exp_string("Hello Brandon! Optional arg is \"default\". Number is \"42\". Required is \"this_was_required\". Any is \"any_value\". extras is \"--extra1=1 --extra2 extra3\"")?;
         Ok(())
     }
@@ -81,11 +83,355 @@ mod interactive {
         }
     }
 
+    fn do_interactive_picker() -> Result<(), Error> {
+        let exec = format!("{} --inkfile tests/picker_case/inkjet.md -i", cargo_bin());
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Pick a command")?;
+        p.send("stop")?;
+        p.flush()?;
+        p.exp_string("services stop")?;
+        p.send("\r")?;
+        p.flush()?;
+        p.exp_string("Execute step stop?")?;
+        p.send("y")?;
+        p.flush()?;
+        p.exp_string("Enter value for name *")?;
+        p.send_line("api")?;
+        p.exp_string("stopping api")?;
+        Ok(())
+    }
+
+    fn do_interactive_edit() -> Result<(), Error> {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let editor = dir.path().join("editor.sh");
+        std::fs::write(&editor, "#!/bin/sh\nsed -i 's/stopping/halting/' \"$1\"\n").unwrap();
+        std::fs::set_permissions(&editor, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        let exec = format!(
+            "env VISUAL={} {} --inkfile tests/picker_case/inkjet.md -i services stop",
+            editor.display(),
+            cargo_bin()
+        );
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Execute step stop?")?;
+        p.send("e")?;
+        p.flush()?;
+        p.exp_string("Enter value for name *")?;
+        p.send_line("api")?;
+        p.exp_string("The edit applies to this run only")?;
+        p.exp_string("Execute step stop?")?;
+        p.send("y")?;
+        p.flush()?;
+        p.exp_string("halting api")?;
+        Ok(())
+    }
+
+    fn do_interactive_remember() -> Result<(), Error> {
+        let data_dir = assert_fs::TempDir::new().unwrap();
+        let exec = format!(
+            "env XDG_DATA_HOME={} {} --inkfile tests/picker_case/inkjet.md -i services stop",
+            data_dir.path().display(),
+            cargo_bin()
+        );
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Execute step stop?")?;
+        p.send("y")?;
+        p.flush()?;
+        p.exp_string("Enter value for name *")?;
+        p.send_line("api")?;
+        p.exp_string("stopping api")?;
+        p.exp_eof()?;
+
+        // The last value is the default of the prompt
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Execute step stop?")?;
+        p.send("y")?;
+        p.flush()?;
+        p.exp_string("Enter value for name * (api)")?;
+        p.send_line("")?;
+        p.exp_string("stopping api")?;
+        p.exp_eof()?;
+
+        let forget = format!(
+            "env XDG_DATA_HOME={} {} --inkfile tests/picker_case/inkjet.md --forget",
+            data_dir.path().display(),
+            cargo_bin()
+        );
+        let mut p = spawn(&forget, Some(6_000))?;
+        p.exp_string("Forgot the answers remembered for")?;
+        p.exp_eof()?;
+        Ok(())
+    }
+
+    fn do_confirm_phrase() -> Result<(), Error> {
+        let exec = format!(
+            "env -u INKJET_YES {} --inkfile tests/confirm_case/inkjet.md reset",
+            cargo_bin()
+        );
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("This deletes prod data. Type prod to continue")?;
+        p.send_line("staging")?;
+        p.exp_string("Did not run reset")?;
+        p.exp_eof()?;
+
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("This deletes prod data. Type prod to continue")?;
+        p.send_line("prod")?;
+        p.exp_string("reset done")?;
+        Ok(())
+    }
+
+    fn do_runbook() -> Result<(), Error> {
+        let state_dir = assert_fs::TempDir::new().unwrap();
+        let exec = format!(
+            "env XDG_STATE_HOME={} {} --inkfile tests/picker_case/inkjet.md --runbook",
+            state_dir.path().display(),
+            cargo_bin()
+        );
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Step 1 of 2: build")?;
+        p.exp_string("(r)un, (s)kip, (p)review, (e)dit or (q)uit")?;
+        p.send("r")?;
+        p.flush()?;
+        p.exp_string("building")?;
+        p.exp_string("Step 2 of 2: services stop")?;
+        p.exp_string("(r)un, (s)kip, (p)review, (e)dit or (q)uit")?;
+        p.send("s")?;
+        p.flush()?;
+        p.exp_string("1 ran, 1 skipped, 0 failed")?;
+        Ok(())
+    }
+
+    fn do_runbook_resume() -> Result<(), Error> {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let inkfile = dir.path().join("inkjet.md");
+        let step = |name: &str| format!("## {name}\n\n```sh\necho \"{name} done\"\n```\n\n");
+        std::fs::write(&inkfile, [step("first"), step("second")].concat()).unwrap();
+        let exec = format!(
+            "env XDG_STATE_HOME={} {} --inkfile {} --runbook --resume",
+            dir.path().display(),
+            cargo_bin(),
+            inkfile.display()
+        );
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Step 1 of 2: first")?;
+        p.send("r")?;
+        p.flush()?;
+        p.exp_string("first done")?;
+        p.exp_string("Step 2 of 2: second")?;
+        p.send("q")?;
+        p.flush()?;
+        p.exp_string("Run again with --runbook --resume to continue")?;
+        p.exp_eof()?;
+
+        // A step added above the saved one does not move the runbook
+        std::fs::write(
+            &inkfile,
+            [step("setup"), step("first"), step("second")].concat(),
+        )
+        .unwrap();
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Resuming at step 3 of 3: second")?;
+        p.send("r")?;
+        p.flush()?;
+        p.exp_string("second done")?;
+        p.exp_eof()?;
+        Ok(())
+    }
+
+    fn do_session_failing_line() -> Result<(), Error> {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let inkfile = dir.path().join("inkjet.md");
+        std::fs::write(
+            &inkfile,
+            "## broken\n\n```sh\necho start\nfalse\necho unreachable\n```\n",
+        )
+        .unwrap();
+        let exec = format!(
+            "env XDG_STATE_HOME={} {} --inkfile {} --runbook --session",
+            dir.path().display(),
+            cargo_bin(),
+            inkfile.display()
+        );
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Step 1 of 1: broken")?;
+        p.send("r")?;
+        p.flush()?;
+        p.exp_string("start")?;
+        let before = p.exp_string("failed (exit 1)")?;
+        assert!(!before.contains("unreachable"), "{before}");
+        p.exp_eof()?;
+        Ok(())
+    }
+
+    fn do_interactive_menus() -> Result<(), Error> {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let inkfile = dir.path().join("inkjet.md");
+        std::fs::write(
+            &inkfile,
+            r#"## deploy (hosts...)
+
+**OPTIONS**
+
+- env
+  - flag: --env
+  - type: string
+  - choices: dev, prod
+- region
+  - flag: --region
+  - type: string
+  - choices: eu, us
+
+```sh
+echo "env=[$env] region=[$region] hosts=[$hosts]"
+```
+"#,
+        )
+        .unwrap();
+        let exec = format!(
+            "env XDG_DATA_HOME={} {} --inkfile {} -i deploy",
+            dir.path().display(),
+            cargo_bin(),
+            inkfile.display()
+        );
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Execute step deploy?")?;
+        p.send("y")?;
+        p.flush()?;
+        // Optional flags start on (none), so moving up picks the last choice
+        p.exp_string("Enter option for env")?;
+        p.send("k")?;
+        p.send_line("")?;
+        p.exp_string("Enter option for region")?;
+        p.send_line("")?;
+        p.exp_string("Enter value for hosts *")?;
+        p.send_line("web1")?;
+        p.exp_string("Enter another value for hosts (empty to finish)")?;
+        p.send_line("web2")?;
+        p.exp_string("Enter another value for hosts (empty to finish)")?;
+        p.send_line("")?;
+        p.exp_string("env=[prod] region=[] hosts=[web1 web2]")?;
+        Ok(())
+    }
+
+    fn do_interactive_secret() -> Result<(), Error> {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let inkfile = dir.path().join("inkjet.md");
+        std::fs::write(
+            &inkfile,
+            r#"## login
+
+**OPTIONS**
+
+- flag: --token |string secret| The API token
+
+```sh
+[ -n "$verbose" ] && echo "verbose login"
+echo "token has ${#token} characters"
+```
+"#,
+        )
+        .unwrap();
+        // cat shows the edit header instead of changing the script
+        let exec = format!(
+            "env NO_COLOR=1 VISUAL=cat XDG_DATA_HOME={} {} --inkfile {} -i login --verbose",
+            dir.path().display(),
+            cargo_bin(),
+            inkfile.display()
+        );
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Execute step login?")?;
+        p.send("e")?;
+        p.flush()?;
+        p.exp_string("Enter option for token")?;
+        p.send_line("hunter2")?;
+        let mut shown = p.exp_string("token=<secret>")?;
+        shown.push_str(&p.exp_string("Execute step login?")?);
+        p.send("y")?;
+        p.flush()?;
+        shown.push_str(&p.exp_string("verbose login")?);
+        shown.push_str(&p.exp_string("token has 7 characters")?);
+        assert!(!shown.contains("hunter2"), "{shown}");
+
+        // The preview shows the script, not the values
+        let mut p = spawn(&format!("{exec} --token hunter2"), Some(6_000))?;
+        p.exp_string("Execute step login?")?;
+        p.send("p")?;
+        p.flush()?;
+        let shown = p.exp_eof()?;
+        assert!(shown.contains("${#token}"), "{shown}");
+        assert!(!shown.contains("hunter2"), "{shown}");
+        Ok(())
+    }
+
+    fn do_session_after_failure() -> Result<(), Error> {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let inkfile = dir.path().join("inkjet.md");
+        std::fs::write(
+            &inkfile,
+            r#"## setup
+
+```sh
+export GREETING=hi
+mkdir -p sub && cd sub
+```
+
+## broken
+
+```sh
+false
+```
+
+## verify
+
+```sh
+echo "greeting=$GREETING dir=$(basename "$PWD")"
+```
+"#,
+        )
+        .unwrap();
+        let exec = format!(
+            "env XDG_DATA_HOME={} {} --inkfile {} -i --session setup",
+            dir.path().display(),
+            cargo_bin(),
+            inkfile.display()
+        );
+        let mut p = spawn(&exec, Some(6_000))?;
+        p.exp_string("Execute step setup?")?;
+        p.send("y")?;
+        p.flush()?;
+        for step in ["broken", "verify"] {
+            p.exp_string("Pick the next step of the session")?;
+            p.send(step)?;
+            p.flush()?;
+            p.exp_string(&format!("> {step}"))?;
+            p.send("\r")?;
+            p.flush()?;
+            p.exp_string(&format!("Execute step {step}?"))?;
+            p.send("y")?;
+            p.flush()?;
+        }
+        // The failure ended the shell, but its replacement keeps the directory and exports
+        p.exp_string("a new one was started")?;
+        p.exp_string("greeting=hi dir=sub")?;
+        Ok(())
+    }
+
     #[test]
     fn interactive() {
         do_interactive().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
     }
 
+    #[test]
+    fn interactive_menus() {
+        do_interactive_menus().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
+
+    #[test]
+    fn interactive_secret() {
+        do_interactive_secret().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
+
     #[test]
     fn interactive_preview() {
         do_interactive_preview().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
@@ -95,4 +441,44 @@ mod interactive {
     fn interactive_skip() {
         do_interactive_skip().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
     }
+
+    #[test]
+    fn interactive_picker() {
+        do_interactive_picker().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
+
+    #[test]
+    fn interactive_edit() {
+        do_interactive_edit().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
+
+    #[test]
+    fn interactive_remember() {
+        do_interactive_remember().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
+
+    #[test]
+    fn confirm_phrase() {
+        do_confirm_phrase().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
+
+    #[test]
+    fn runbook() {
+        do_runbook().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
+
+    #[test]
+    fn session_failing_line() {
+        do_session_failing_line().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
+
+    #[test]
+    fn session_after_failure() {
+        do_session_after_failure().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
+
+    #[test]
+    fn runbook_resume() {
+        do_runbook_resume().unwrap_or_else(|e| panic!("inkjet job failed with {e}"));
+    }
 }
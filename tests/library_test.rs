@@ -0,0 +1,201 @@
+// Copyright 2025 Brandon Kalinowski (brandonkal)
+// SPDX-License-Identifier: MIT
+
+use inkjet::command::Script;
+use inkjet::executor::{ExecContext, Executor, ExecutorRegistry, PreparedCommand};
+use inkjet::{Error, ExecutionRequest, Inkfile};
+use std::io;
+use std::process::Command;
+
+mod common;
+pub use common::*;
+
+const INKFILE: &str = r#"
+## greet//hi (name) (greeting=Hello)
+
+**OPTIONS**
+
+- flag: --times |number| How many times
+- flag: --loud |bool| Shout
+
+```sh
+for i in $(seq ${times:-1}); do
+  if [ -n "$loud" ]; then
+    echo "$greeting $name!" | tr '[:lower:]' '[:upper:]'
+  else
+    echo "$greeting $name"
+  fi
+done
+echo "$EXTRA" >&2
+```
+
+## services
+
+### services start (service)
+
+```sh
+echo "starting $service in $(basename "$PWD")"
+```
+"#;
+
+#[test]
+fn finds_commands_by_path_and_alias() {
+    let inkfile = Inkfile::parse(INKFILE).expect("valid inkfile");
+    assert_eq!(
+        inkfile.find("greet").map(|c| c.name.as_str()),
+        Some("greet")
+    );
+    assert_eq!(inkfile.find("hi").map(|c| c.name.as_str()), Some("greet"));
+    let start = inkfile.find("services start").expect("nested command");
+    assert_eq!(start.name, "start");
+    assert!(inkfile.find("services stop").is_none());
+}
+
+#[test]
+fn runs_with_args_flags_and_env() {
+    let (_temp, inkfile_path) = common::inkfile(INKFILE);
+    let inkfile = Inkfile::load(&inkfile_path).expect("valid inkfile");
+    let result = ExecutionRequest::new(&inkfile, "greet")
+        .expect("command exists")
+        .arg("world")
+        .flag("times", "2")
+        .flag("--loud", "true")
+        .env("EXTRA", "captured")
+        .run()
+        .expect("command runs");
+    assert!(result.success());
+    assert_eq!(result.stdout, "HELLO WORLD!\nHELLO WORLD!\n");
+    assert_eq!(result.stderr, "captured\n");
+}
+
+#[test]
+fn runs_in_custom_directory() {
+    let (temp, inkfile_path) = common::inkfile(INKFILE);
+    let other = temp.path().join("other");
+    std::fs::create_dir(&other).unwrap();
+    let inkfile = Inkfile::load(&inkfile_path).expect("valid inkfile");
+    let result = ExecutionRequest::new(&inkfile, "services start")
+        .expect("command exists")
+        .arg("api")
+        .cwd(&other)
+        .run()
+        .expect("command runs");
+    assert_eq!(result.stdout, "starting api in other\n");
+}
+
+#[test]
+fn returns_errors_instead_of_printing() {
+    let inkfile = Inkfile::parse(INKFILE).expect("valid inkfile");
+    let err = ExecutionRequest::new(&inkfile, "deploy").expect_err("no such command");
+    assert!(matches!(err, Error::CommandNotFound(_)));
+
+    let greet = ExecutionRequest::new(&inkfile, "greet").expect("command exists");
+    let err = greet.clone().run().expect_err("name is required");
+    assert!(matches!(err, Error::MissingArg { ref arg, .. } if arg == "name"));
+    assert_eq!(err.exit_code(), 2);
+
+    let err = greet
+        .clone()
+        .arg("world")
+        .flag("times", "many")
+        .run()
+        .expect_err("times is a number");
+    assert_eq!(err.to_string(), "flag `times` expects a numerical value");
+
+    let err = greet
+        .arg("world")
+        .flag("color", "red")
+        .run()
+        .expect_err("unknown flag");
+    assert!(matches!(err, Error::UnknownFlag { .. }));
+
+    let err = Inkfile::parse("## bad name\n\n```\necho\n```\n").expect_err("spaces in name");
+    assert!(matches!(err, Error::Parse(_)));
+    assert_eq!(err.exit_code(), 78);
+}
+
+/// Echoes the script in upper case with the name of the command
+struct Shout;
+
+impl Executor for Shout {
+    fn prepare(&self, script: &Script, ctx: &ExecContext) -> io::Result<PreparedCommand> {
+        let mut command = Command::new("echo");
+        command.arg(format!(
+            "{}: {}",
+            ctx.command,
+            script.source.trim().to_uppercase()
+        ));
+        Ok(PreparedCommand::new(command, "echo"))
+    }
+}
+
+#[test]
+fn runs_custom_executors() {
+    let inkfile =
+        Inkfile::parse("## hello\n\n```shout\nhello $name\n```\n").expect("valid inkfile");
+    let mut registry = ExecutorRegistry::new();
+    registry.register("shout", Shout);
+    let result = ExecutionRequest::new(&inkfile, "hello")
+        .expect("command exists")
+        .executors(&registry)
+        .run()
+        .expect("command runs");
+    assert_eq!(result.stdout, "hello: HELLO $NAME\n");
+}
+
+#[test]
+fn runs_blocks_without_a_language_with_the_sh_executor() {
+    let inkfile = Inkfile::parse("## hello\n\n```\nhello $name\n```\n").expect("valid inkfile");
+    let mut registry = ExecutorRegistry::new();
+    registry.register("sh", Shout);
+    let result = ExecutionRequest::new(&inkfile, "hello")
+        .expect("command exists")
+        .executors(&registry)
+        .run()
+        .expect("command runs");
+    assert_eq!(result.stdout, "hello: HELLO $NAME\n");
+}
+
+#[test]
+fn saves_edited_scripts() {
+    let (_temp, inkfile_path) = common::inkfile(INKFILE);
+    let mut inkfile = Inkfile::load(&inkfile_path).unwrap();
+    let greet = inkfile.find("greet").unwrap().clone();
+    let written = inkfile.save_script(&greet, "echo \"$greeting\"").unwrap();
+    assert_eq!(written, inkfile.path);
+
+    // The save moved the commands below greet, and a command can be saved more than once
+    for source in ["echo \"launching $service\"", "echo \"booting $service\""] {
+        let start = inkfile.find("services start").unwrap().clone();
+        inkfile.save_script(&start, source).unwrap();
+    }
+
+    let saved = Inkfile::load(&inkfile_path).unwrap();
+    assert_eq!(
+        saved.find("greet").unwrap().script.source,
+        "echo \"$greeting\"\n"
+    );
+    assert_eq!(
+        saved.find("services start").unwrap().script.source,
+        "echo \"booting $service\"\n"
+    );
+    assert_eq!(
+        saved.find("services start").unwrap().script.source,
+        inkfile.find("services start").unwrap().script.source
+    );
+
+    // The file changed on disk, so the stale tree must not overwrite it
+    let mut stale = saved.clone();
+    std::fs::write(&inkfile_path, INKFILE).unwrap();
+    let start = stale.find("services start").unwrap().clone();
+    let err = stale.save_script(&start, "echo stale").unwrap_err();
+    assert!(
+        err.to_string().contains("changed since it was read"),
+        "{err}"
+    );
+
+    let mut elsewhere = start.clone();
+    elsewhere.inkjet_file = "/missing/inkjet.md".to_string();
+    let err = stale.save_script(&elsewhere, "echo lost").unwrap_err();
+    assert!(matches!(err, Error::SourceNotFound { .. }), "{err}");
+}